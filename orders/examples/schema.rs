@@ -3,7 +3,7 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use universe_orders::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, OrderAddressesResponse, OrderForAddressIdsResponse, OrderResponse, OrdersResponse, ConfigResponse};
+use universe_orders::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, OrderAddressesResponse, OrderForAddressIdsResponse, OrderResponse, OrdersResponse, EscrowResponse, FeesCollectedResponse, PoolsResponse, OrderFillsResponse, OrderListResponse, ConfigResponse, SimulateLimitResponse, SimulateSmartResponse, SimulateGridResponse};
 
 fn main() {
   let mut out_dir = current_dir().unwrap();
@@ -19,5 +19,13 @@ fn main() {
   export_schema(&schema_for!(OrderResponse), &out_dir);
   export_schema(&schema_for!(OrdersResponse), &out_dir);
   export_schema(&schema_for!(ConfigResponse), &out_dir);
+  export_schema(&schema_for!(EscrowResponse), &out_dir);
+  export_schema(&schema_for!(FeesCollectedResponse), &out_dir);
+  export_schema(&schema_for!(PoolsResponse), &out_dir);
+  export_schema(&schema_for!(OrderFillsResponse), &out_dir);
+  export_schema(&schema_for!(OrderListResponse), &out_dir);
+  export_schema(&schema_for!(SimulateLimitResponse), &out_dir);
+  export_schema(&schema_for!(SimulateSmartResponse), &out_dir);
+  export_schema(&schema_for!(SimulateGridResponse), &out_dir);
  
 }
@@ -1,20 +1,24 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, from_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Addr, Order
+    to_binary, from_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, StdError, Addr, Order, Reply, ReplyOn, SubMsg, Uint128, Storage
 };
-use cw_storage_plus::Bound;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use cw_storage_plus::{Bound, IndexedMap, Map, PrimaryKey};
 use cw_utils::maybe_addr;
 use cw2::{get_contract_version, set_contract_version};
 use cw20::{Balance, Cw20CoinVerified, Cw20ReceiveMsg, Denom};
 use crate::error::ContractError;
 use crate::msg::{
-    ConfigResponse, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg, OrderAddressesResponse, OrderForAddressIdsResponse, OrderResponse, OrdersResponse, LimitConfig, SmartConfig, GridConfig
+    ConfigResponse, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg, OrderAmendment, OrderAddressesResponse, OrderForAddressIdsResponse, OrderResponse, OrdersResponse, EscrowResponse, FeesCollectedResponse, PoolsResponse, OrderFillsResponse, OrderListResponse, OrderType, OrderConfig
 };
 use crate::state::{
-    Config, CONFIG, LIMIT_ORDERS, LIMIT_ORDERS_COUNT, SMART_ORDERS, SMART_ORDERS_COUNT, GRID_ORDERS, GRID_ORDERS_COUNT
+    Config, CONFIG, POOLS, ORDER_FILLS, OrderIndexes, limit_orders, smart_orders, grid_orders, dca_orders, twap_orders, bracket_orders,
+    LIMIT_ORDERS_ARCHIVE, SMART_ORDERS_ARCHIVE, GRID_ORDERS_ARCHIVE, DCA_ORDERS_ARCHIVE, TWAP_ORDERS_ARCHIVE, BRACKET_ORDERS_ARCHIVE
 };
 
+use crate::legacy;
 use crate::ordergroup;
 use crate::util;
 
@@ -27,13 +31,17 @@ pub fn instantiate(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
-    _msg: InstantiateMsg,
+    msg: InstantiateMsg,
 ) -> StdResult<Response> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     let config = Config {
         owner: info.sender.clone(),
-        enabled: true
+        enabled: true,
+        max_slippage_bps: util::DEFAULT_MAX_SLIPPAGE_BPS,
+        keeper_fee_bps: util::DEFAULT_KEEPER_FEE_BPS,
+        performance_fee_bps: util::DEFAULT_PERFORMANCE_FEE_BPS,
+        fee_recipient: msg.treasury_address
     };
     CONFIG.save(deps.storage, &config)?;
 
@@ -50,43 +58,150 @@ pub fn execute(
     match msg {
         ExecuteMsg::UpdateOwner { owner } => util::execute_update_owner(deps.storage, info.sender, owner),
         ExecuteMsg::UpdateEnabled { enabled } => util::execute_update_enabled(deps.storage, info.sender, enabled),
-        ExecuteMsg::Receive( msg ) => execute_receive(deps, info, msg),
-
-        ExecuteMsg::Stop { order_type, id } => {
-            if order_type == 0u64 {
-                ordergroup::stop_limit(deps, info.sender, id)
-            } else if order_type == 1u64 {
-                ordergroup::stop_smart(deps, info.sender, id)
-            } else if order_type == 2u64 {
-                ordergroup::stop_grid(deps, info.sender, id)
-            } else {
-                ordergroup::stop_grid(deps, info.sender, id)
-            }
+        ExecuteMsg::UpdateMaxSlippage { max_slippage_bps } => util::execute_update_max_slippage(deps.storage, info.sender, max_slippage_bps),
+        ExecuteMsg::UpdateKeeperFee { keeper_fee_bps } => util::execute_update_keeper_fee(deps.storage, info.sender, keeper_fee_bps),
+        ExecuteMsg::UpdatePerformanceFee { performance_fee_bps, fee_recipient } => util::execute_update_performance_fee(deps.storage, info.sender, performance_fee_bps, fee_recipient),
+        ExecuteMsg::Receive( msg ) => execute_receive(deps, env, info, msg),
+
+        ExecuteMsg::Stop { order_type, id } => match order_type {
+            OrderType::Limit => ordergroup::stop_limit(deps, env, info.sender, id),
+            OrderType::Smart => ordergroup::stop_smart(deps, env, info.sender, id),
+            OrderType::Grid => ordergroup::stop_grid(deps, env, info.sender, id),
+            OrderType::Dca => ordergroup::stop_dca(deps, env, info.sender, id),
+            OrderType::Twap => ordergroup::stop_twap(deps, env, info.sender, id),
+            OrderType::Bracket => ordergroup::stop_bracket(deps, env, info.sender, id)
         },
-        ExecuteMsg::Sync { order_type, address, id } => {
-            if order_type == 0u64 {
-                ordergroup::sync_limit(deps, info.sender, address, id, false)
-            } else if order_type == 1u64 {
-                ordergroup::sync_smart(deps, info.sender, address, id, false)
-            } else if order_type == 2u64 {
-                ordergroup::sync_grid(deps, info.sender, address, id, false)
-            } else {
-                ordergroup::sync_grid(deps, info.sender, address, id, false)
-            }
-        },
-        ExecuteMsg::StartLimit( msg ) => ordergroup::start_limit(deps, msg, Balance::from(info.funds), info.sender),
-        ExecuteMsg::StartSmart( msg ) => ordergroup::start_smart(deps, msg, Balance::from(info.funds), info.sender),
-        ExecuteMsg::StartGrid( msg ) => ordergroup::start_grid(deps, msg, Balance::from(info.funds), info.sender),
+        ExecuteMsg::Sync { order_type, address, id } => execute_sync(deps, env, info.sender, order_type, address, id),
+        ExecuteMsg::SyncBatch { orders } => execute_sync_batch(deps, env, info.sender, orders),
+        ExecuteMsg::StartLimit( msg ) => ordergroup::start_limit(deps, env, msg, Balance::from(info.funds), info.sender),
+        ExecuteMsg::StartSmart( msg ) => ordergroup::start_smart(deps, env, msg, Balance::from(info.funds), info.sender),
+        ExecuteMsg::StartGrid( msg ) => ordergroup::start_grid(deps, env, msg, Balance::from(info.funds), info.sender),
+        ExecuteMsg::StartDca( msg ) => ordergroup::start_dca(deps, env, msg, Balance::from(info.funds), info.sender),
+        ExecuteMsg::StartTwap( msg ) => ordergroup::start_twap(deps, env, msg, Balance::from(info.funds), info.sender),
+        ExecuteMsg::StartBracket( msg ) => ordergroup::start_bracket(deps, env, msg, Balance::from(info.funds), info.sender),
+
+        ExecuteMsg::IncreaseOrder { order_type, id } => execute_increase_order(deps, env, info.sender, order_type, id, Balance::from(info.funds)),
+        ExecuteMsg::DecreaseOrder { order_type, id, amount } => execute_decrease_order(deps, info.sender, order_type, id, amount),
+        ExecuteMsg::AmendOrder { id, amendment } => execute_amend_order(deps, env, info.sender, id, amendment),
+
+        ExecuteMsg::Withdraw { denom } => execute_withdraw(deps, env, info, denom),
+
+        ExecuteMsg::AddPool { pool_address, dex } => util::execute_add_pool(deps.storage, deps.querier, info.sender, pool_address, dex.unwrap_or_default()),
+        ExecuteMsg::RemovePool { pool_address } => util::execute_remove_pool(deps.storage, info.sender, pool_address),
+        ExecuteMsg::UpdatePoolEnabled { pool_address, enabled } => util::execute_update_pool_enabled(deps.storage, info.sender, pool_address, enabled)
+
+
+    }
+}
+
+
+pub fn execute_sync(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    order_type: OrderType,
+    address: Option<Addr>,
+    id: u64
+) -> Result<Response, ContractError> {
+    match order_type {
+        OrderType::Limit => ordergroup::sync_limit(deps, env, caller, address, id, false),
+        OrderType::Smart => ordergroup::sync_smart(deps, env, caller, address, id, false),
+        OrderType::Grid => ordergroup::sync_grid(deps, env, caller, address, id, false),
+        OrderType::Dca => ordergroup::sync_dca(deps, env, caller, address, id, false),
+        OrderType::Twap => ordergroup::sync_twap(deps, env, caller, address, id, false),
+        OrderType::Bracket => ordergroup::sync_bracket(deps, env, caller, address, id, false)
+    }
+}
+
+pub fn execute_sync_batch(
+    mut deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    orders: Vec<(OrderType, Addr, u64)>
+) -> Result<Response, ContractError> {
+    if orders.is_empty() || orders.len() > util::MAX_SYNC_BATCH {
+        return Err(ContractError::InvalidInput {});
+    }
 
-        ExecuteMsg::Withdraw { denom } => execute_withdraw(deps, env, info, denom)
+    let mut response = Response::new().add_attribute("action", "sync_batch");
+    let mut transfers: Vec<SubMsg> = vec![];
+    for (order_type, address, id) in orders {
+        let outcome = match execute_sync(deps.branch(), env.clone(), caller.clone(), order_type, Some(address.clone()), id) {
+            Ok(res) => {
+                let action = res.attributes.iter()
+                    .find(|attr| attr.key == "action")
+                    .map(|attr| attr.value.clone())
+                    .unwrap_or_default();
+                // plain transfers go after every swap, so the swap outputs measured in the replies stay exact
+                if res.messages.iter().any(|sub_msg| sub_msg.reply_on != ReplyOn::Never) {
+                    response = response.add_submessages(res.messages);
+                } else {
+                    transfers.extend(res.messages);
+                }
+                action
+            },
+            // finished or removed orders must not abort the other syncs, nor orders priced out of their tolerance
+            Err(ContractError::AlreadyFinishedOrder {}) => String::from("skipped_finished"),
+            Err(ContractError::OrderNotExist {}) => String::from("skipped_not_exist"),
+            Err(ContractError::SlippageExceeded {}) => String::from("skipped_slippage"),
+            Err(err) => return Err(err)
+        };
+        response = response.add_attribute(format!("{}_{}_{}", order_type, address, id), outcome);
+    }
 
+    Ok(response.add_submessages(transfers))
+}
 
+// Only limit, smart and grid orders can change their size
+pub fn execute_increase_order(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    order_type: OrderType,
+    id: u64,
+    balance: Balance
+) -> Result<Response, ContractError> {
+    match order_type {
+        OrderType::Limit => ordergroup::increase_limit(deps, env, address, id, balance),
+        OrderType::Smart => ordergroup::increase_smart(deps, env, address, id, balance),
+        OrderType::Grid => ordergroup::increase_grid(deps, address, id, balance),
+        OrderType::Dca | OrderType::Twap | OrderType::Bracket => Err(ContractError::UnsupportedOrderType { order_type })
     }
 }
 
+pub fn execute_decrease_order(
+    deps: DepsMut,
+    address: Addr,
+    order_type: OrderType,
+    id: u64,
+    amount: Uint128
+) -> Result<Response, ContractError> {
+    match order_type {
+        OrderType::Limit => ordergroup::decrease_limit(deps, address, id, amount),
+        OrderType::Smart => ordergroup::decrease_smart(deps, address, id, amount),
+        OrderType::Grid => ordergroup::decrease_grid(deps, address, id, amount),
+        OrderType::Dca | OrderType::Twap | OrderType::Bracket => Err(ContractError::UnsupportedOrderType { order_type })
+    }
+}
+
+// The amendment variant picks the order type and the fields it may change
+pub fn execute_amend_order(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64,
+    amendment: OrderAmendment
+) -> Result<Response, ContractError> {
+    match amendment {
+        OrderAmendment::Limit(amendment) => ordergroup::amend_limit(deps, env, address, id, amendment),
+        OrderAmendment::Smart(amendment) => ordergroup::amend_smart(deps, env, address, id, amendment),
+        OrderAmendment::Grid(amendment) => ordergroup::amend_grid(deps, env, address, id, amendment)
+    }
+}
 
 pub fn execute_receive(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     wrapper: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
@@ -101,13 +216,25 @@ pub fn execute_receive(
     let api = deps.api;
     match msg {
         ReceiveMsg::Limit(msg) => {
-            ordergroup::start_limit(deps, msg, balance, api.addr_validate(&wrapper.sender)?)
+            ordergroup::start_limit(deps, env, msg, balance, api.addr_validate(&wrapper.sender)?)
         },
         ReceiveMsg::Smart(msg) => {
-            ordergroup::start_smart(deps, msg, balance, api.addr_validate(&wrapper.sender)?)
+            ordergroup::start_smart(deps, env, msg, balance, api.addr_validate(&wrapper.sender)?)
         },
         ReceiveMsg::Grid(msg) => {
-            ordergroup::start_grid(deps, msg, balance, api.addr_validate(&wrapper.sender)?)
+            ordergroup::start_grid(deps, env, msg, balance, api.addr_validate(&wrapper.sender)?)
+        },
+        ReceiveMsg::Dca(msg) => {
+            ordergroup::start_dca(deps, env, msg, balance, api.addr_validate(&wrapper.sender)?)
+        },
+        ReceiveMsg::Twap(msg) => {
+            ordergroup::start_twap(deps, env, msg, balance, api.addr_validate(&wrapper.sender)?)
+        },
+        ReceiveMsg::Bracket(msg) => {
+            ordergroup::start_bracket(deps, env, msg, balance, api.addr_validate(&wrapper.sender)?)
+        },
+        ReceiveMsg::IncreaseOrder { order_type, id } => {
+            execute_increase_order(deps, env, api.addr_validate(&wrapper.sender)?, order_type, id, balance)
         }
     }
 }
@@ -120,7 +247,13 @@ pub fn execute_withdraw(
 ) -> Result<Response, ContractError> {
 
     util::check_owner(deps.storage, info.sender.clone())?;
-    let amount = util::get_token_amount(deps.querier, denom.clone(), env.contract.address.clone())?;
+    let balance = util::get_token_amount(deps.querier, denom.clone(), env.contract.address.clone())?;
+
+    // funds of the active orders stay in the contract
+    let amount = balance.saturating_sub(util::get_escrowed(deps.storage, &denom)?);
+    if amount.is_zero() {
+        return Err(ContractError::NothingToWithdraw {});
+    }
     let message = util::transfer_token_message(deps.querier, denom.clone(), amount, info.sender.clone())?;
 
     Ok(Response::new()
@@ -132,7 +265,15 @@ pub fn execute_withdraw(
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        util::SWAP_REPLY_ID => ordergroup::reply_swap(deps, env),
+        id => Err(ContractError::UnknownReplyId { id })
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} 
             => to_binary(&query_config(deps)?),
@@ -145,6 +286,28 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             => to_binary(&query_order(deps, order_type, address, id)?),
         QueryMsg::Orders { order_type, address} 
             => to_binary(&query_orders(deps, order_type, address)?),
+        QueryMsg::Escrow { denom }
+            => to_binary(&query_escrow(deps, env, denom)?),
+        QueryMsg::FeesCollected { denom }
+            => to_binary(&query_fees_collected(deps, denom)?),
+        QueryMsg::Pools { start_after, limit }
+            => to_binary(&query_pools(deps, start_after, limit)?),
+        QueryMsg::OrderFills { order_type, address, id, start_after, limit }
+            => to_binary(&query_order_fills(deps, order_type, address, id, start_after, limit)?),
+        QueryMsg::OrdersByPool { order_type, pool_address, start_after, limit }
+            => to_binary(&query_indexed_orders(deps, order_type, OrderIndex::Pool(pool_address), start_after, limit).map_err(|err| StdError::generic_err(err.to_string()))?),
+        QueryMsg::OrdersByDenom { order_type, token1_denom, start_after, limit }
+            => to_binary(&query_indexed_orders(deps, order_type, OrderIndex::Token1Denom(util::denom_key(&token1_denom)), start_after, limit).map_err(|err| StdError::generic_err(err.to_string()))?),
+        QueryMsg::ActiveOrders { order_type, start_after, limit }
+            => to_binary(&query_indexed_orders(deps, order_type, OrderIndex::Finished(0u8), start_after, limit).map_err(|err| StdError::generic_err(err.to_string()))?),
+        QueryMsg::ArchivedOrders { order_type, address, start_after, limit }
+            => to_binary(&query_archived_orders(deps, order_type, address, start_after, limit)?),
+        QueryMsg::SimulateLimit { msg, amount }
+            => to_binary(&ordergroup::simulate_limit(deps, env, msg, amount).map_err(|err| StdError::generic_err(err.to_string()))?),
+        QueryMsg::SimulateSmart { msg, amount }
+            => to_binary(&ordergroup::simulate_smart(deps, env, msg, amount).map_err(|err| StdError::generic_err(err.to_string()))?),
+        QueryMsg::SimulateGrid { msg, amount }
+            => to_binary(&ordergroup::simulate_grid(deps, env, msg, amount).map_err(|err| StdError::generic_err(err.to_string()))?),
         
     }
 }
@@ -153,10 +316,157 @@ pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
     let cfg = CONFIG.load(deps.storage)?;
     Ok(ConfigResponse {
         owner: cfg.owner,
+        max_slippage_bps: cfg.max_slippage_bps,
+        keeper_fee_bps: cfg.keeper_fee_bps,
+        performance_fee_bps: cfg.performance_fee_bps,
+        fee_recipient: cfg.fee_recipient,
+    })
+}
+
+
+pub fn query_escrow(deps: Deps, env: Env, denom: Denom) -> StdResult<EscrowResponse> {
+    let balance = util::get_token_amount(deps.querier, denom.clone(), env.contract.address)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let escrowed = util::get_escrowed(deps.storage, &denom)?;
+    Ok(EscrowResponse {
+        denom,
+        balance,
+        escrowed,
+        surplus: balance.saturating_sub(escrowed),
     })
 }
 
+pub fn query_fees_collected(deps: Deps, denom: Denom) -> StdResult<FeesCollectedResponse> {
+    let amount = util::get_fees_collected(deps.storage, &denom)?;
+    Ok(FeesCollectedResponse { denom, amount })
+}
+
+pub fn query_pools(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<PoolsResponse> {
+    let limit = limit.unwrap_or(util::DEFAULT_LIMIT).min(util::MAX_LIMIT) as usize;
+    let addr = maybe_addr(deps.api, start_after)?;
+    let start = addr.map(|addr| Bound::exclusive(addr.as_ref()));
+
+    let pools:StdResult<Vec<_>> = POOLS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_pool_address, pool)| pool))
+        .collect();
+
+    Ok(PoolsResponse { pools: pools? })
+}
+
+pub fn query_order_fills(
+    deps: Deps,
+    order_type: OrderType,
+    address: Addr,
+    id: u64,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrderFillsResponse> {
+    let limit = limit.unwrap_or(util::DEFAULT_LIMIT).min(util::MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive_int);
+
+    let fills:StdResult<Vec<_>> = ORDER_FILLS
+        .prefix(((address, order_type.key()), id))
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_seq, fill)| fill))
+        .collect();
+
+    Ok(OrderFillsResponse { fills: fills? })
+}
+
+fn range_archive<T: Serialize + DeserializeOwned>(
+    storage: &dyn Storage,
+    archive: Map<(Addr, u64), T>,
+    address: &Addr,
+    start: Option<Bound>,
+    limit: usize,
+    tag: fn(T) -> OrderConfig
+) -> StdResult<Vec<OrderResponse>> {
+    archive
+        .prefix(address.clone())
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, order)| OrderResponse { address: address.clone(), id, order: tag(order) }))
+        .collect()
+}
+
+// Finished orders of an address, oldest id first
+pub fn query_archived_orders(
+    deps: Deps,
+    order_type: OrderType,
+    address: Addr,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrderListResponse> {
+    let limit = limit.unwrap_or(util::DEFAULT_LIMIT).min(util::MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive_int);
+
+    let orders = match order_type {
+        OrderType::Limit => range_archive(deps.storage, LIMIT_ORDERS_ARCHIVE, &address, start, limit, OrderConfig::Limit)?,
+        OrderType::Smart => range_archive(deps.storage, SMART_ORDERS_ARCHIVE, &address, start, limit, OrderConfig::Smart)?,
+        OrderType::Grid => range_archive(deps.storage, GRID_ORDERS_ARCHIVE, &address, start, limit, OrderConfig::Grid)?,
+        OrderType::Dca => range_archive(deps.storage, DCA_ORDERS_ARCHIVE, &address, start, limit, OrderConfig::Dca)?,
+        OrderType::Twap => range_archive(deps.storage, TWAP_ORDERS_ARCHIVE, &address, start, limit, OrderConfig::Twap)?,
+        OrderType::Bracket => range_archive(deps.storage, BRACKET_ORDERS_ARCHIVE, &address, start, limit, OrderConfig::Bracket)?
+    };
 
+    Ok(OrderListResponse { orders })
+}
+
+// Secondary index of the active orders and the value looked up
+pub enum OrderIndex {
+    Pool(Addr),
+    Token1Denom(String),
+    Finished(u8)
+}
+
+fn range_order_index<T: Serialize + DeserializeOwned + Clone>(
+    storage: &dyn Storage,
+    indexes: &OrderIndexes<T>,
+    index: &OrderIndex,
+    start: Option<Bound>,
+    limit: usize,
+    tag: fn(T) -> OrderConfig
+) -> StdResult<Vec<OrderResponse>> {
+    let prefix = match index {
+        OrderIndex::Pool(pool_address) => indexes.pool.prefix(pool_address.clone()),
+        OrderIndex::Token1Denom(denom_key) => indexes.token1_denom.prefix(denom_key.clone()),
+        OrderIndex::Finished(finished) => indexes.finished.prefix(*finished)
+    };
+    prefix
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((address, id), order)| OrderResponse { address, id, order: tag(order) }))
+        .collect()
+}
+
+pub fn query_indexed_orders(
+    deps: Deps,
+    order_type: OrderType,
+    index: OrderIndex,
+    start_after: Option<(Addr, u64)>,
+    limit: Option<u32>,
+) -> Result<OrderListResponse, ContractError> {
+    let limit = limit.unwrap_or(util::DEFAULT_LIMIT).min(util::MAX_LIMIT) as usize;
+    let start = start_after.map(|key| Bound::exclusive(key.joined_key()));
+
+    let orders = match order_type {
+        OrderType::Limit => range_order_index(deps.storage, &limit_orders().idx, &index, start, limit, OrderConfig::Limit)?,
+        OrderType::Smart => range_order_index(deps.storage, &smart_orders().idx, &index, start, limit, OrderConfig::Smart)?,
+        OrderType::Grid => range_order_index(deps.storage, &grid_orders().idx, &index, start, limit, OrderConfig::Grid)?,
+        OrderType::Dca => range_order_index(deps.storage, &dca_orders().idx, &index, start, limit, OrderConfig::Dca)?,
+        OrderType::Twap => range_order_index(deps.storage, &twap_orders().idx, &index, start, limit, OrderConfig::Twap)?,
+        OrderType::Bracket => range_order_index(deps.storage, &bracket_orders().idx, &index, start, limit, OrderConfig::Bracket)?
+    };
+
+    Ok(OrderListResponse { orders })
+}
 
 fn map_orders_count(
     item: StdResult<(Addr, (Vec<u64>, u64))>,
@@ -168,7 +478,7 @@ fn map_orders_count(
 
 pub fn query_order_addresses(
     deps: Deps,
-    order_type: u64,
+    order_type: OrderType,
     start_after: Option<String>,
     limit: Option<u32>,
 ) -> StdResult<OrderAddressesResponse> {
@@ -176,15 +486,7 @@ pub fn query_order_addresses(
     let addr = maybe_addr(deps.api, start_after)?;
     let start = addr.map(|addr| Bound::exclusive(addr.as_ref()));
 
-    let mut store = LIMIT_ORDERS_COUNT;
-    if order_type == 0u64 {
-        store = LIMIT_ORDERS_COUNT;
-    } else if order_type == 1u64 {
-        store = SMART_ORDERS_COUNT;
-    } else if order_type == 2u64 {
-        store = GRID_ORDERS_COUNT;
-    }
-    let addresses:StdResult<Vec<_>> = store.clone()
+    let addresses:StdResult<Vec<_>> = ordergroup::orders_count(order_type)
         .range(deps.storage, start, None, Order::Ascending)
         .take(limit)
         .map(|item| map_orders_count(item))
@@ -195,135 +497,97 @@ pub fn query_order_addresses(
 
 pub fn query_order_for_address_ids(
     deps: Deps,
-    order_type: u64,
+    order_type: OrderType,
     address: Addr,
 ) -> StdResult<OrderForAddressIdsResponse> {
-
-    let mut store = LIMIT_ORDERS_COUNT;
-    if order_type == 0u64 {
-        store = LIMIT_ORDERS_COUNT;
-    } else if order_type == 1u64 {
-        store = SMART_ORDERS_COUNT;
-    } else if order_type == 2u64 {
-        store = GRID_ORDERS_COUNT;
-    }
-    let (ids, _max_id) = store.clone().load(deps.storage, address.clone())?;
+    let (ids, _max_id) = ordergroup::orders_count(order_type).load(deps.storage, address.clone())?;
 
     Ok(OrderForAddressIdsResponse { address, ids })
 }
 
 pub fn query_order(
     deps: Deps,
-    order_type: u64,
+    order_type: OrderType,
     address: Addr,
     id: u64
 ) -> StdResult<OrderResponse> {
-
-    if order_type == 0u64 {
-        let limit_order = LIMIT_ORDERS.load(deps.storage, (address.clone(), id))?;
-        return Ok(OrderResponse {
-            address: address.clone(),
-            id,
-            limit_order: Some(limit_order),
-            smart_order: None,
-            grid_order: None
-        });
-    } else if order_type == 1u64 {
-        let smart_order = SMART_ORDERS.load(deps.storage, (address.clone(), id))?;
-        return Ok(OrderResponse {
-            address: address.clone(),
-            id,
-            smart_order: Some(smart_order),
-            limit_order: None,
-            grid_order: None
-        });
-    } else if order_type == 2u64 {
-        let grid_order = GRID_ORDERS.load(deps.storage, (address.clone(), id))?;
-        return Ok(OrderResponse {
-            address: address.clone(),
-            id,
-            grid_order: Some(grid_order),
-            smart_order: None,
-            limit_order: None
-        });
-    } else {
-        return Ok(OrderResponse {
-            address: address.clone(),
-            id,
-            grid_order: None,
-            smart_order: None,
-            limit_order: None
-        });
-    }
-    
-    
+    let order = ordergroup::load_order(deps.storage, order_type, &address, id)?;
+    Ok(OrderResponse { address, id, order })
 }
 
-
+// Active orders of the address, of one type or of all of them
 pub fn query_orders(
     deps: Deps,
-    order_type: u64,
+    order_type: Option<OrderType>,
     address: Addr
 ) -> StdResult<OrdersResponse> {
+    let order_types = match order_type {
+        Some(order_type) => vec![order_type],
+        None => OrderType::ALL.to_vec()
+    };
 
-    
-    if order_type == 0u64 {
-        let (ids, _max_id) = LIMIT_ORDERS_COUNT.load(deps.storage, address.clone())?;
-        let mut list:Vec<LimitConfig> = vec![];
-        for i in ids {
-            list.push(LIMIT_ORDERS.load(deps.storage, (address.clone(), i))?);
-        }
-        return Ok(OrdersResponse {
-            address: address.clone(),
-            limit_orders: Some(list),
-            smart_orders: None,
-            grid_orders: None
-        });
-    } else if order_type == 1u64 {
-        let (ids, _max_id) = SMART_ORDERS_COUNT.load(deps.storage, address.clone())?;
-        let mut list:Vec<SmartConfig> = vec![];
-        for i in ids {
-            list.push(SMART_ORDERS.load(deps.storage, (address.clone(), i))?);
-        }
-        return Ok(OrdersResponse {
-            address: address.clone(),
-            smart_orders: Some(list),
-            limit_orders: None,
-            grid_orders: None
-        });
-    } else if order_type == 2u64 {
-        let (ids, _max_id) = GRID_ORDERS_COUNT.load(deps.storage, address.clone())?;
-        let mut list:Vec<GridConfig> = vec![];
-        for i in ids {
-            list.push(GRID_ORDERS.load(deps.storage, (address.clone(), i))?);
+    let mut orders:Vec<OrderResponse> = vec![];
+    for order_type in order_types {
+        let (ids, _max_id) = ordergroup::orders_count(order_type).may_load(deps.storage, address.clone())?.unwrap_or((vec![], 0));
+        for id in ids {
+            orders.push(OrderResponse {
+                address: address.clone(),
+                id,
+                order: ordergroup::load_order(deps.storage, order_type, &address, id)?
+            });
         }
-        return Ok(OrdersResponse {
-            address: address.clone(),
-            grid_orders: Some(list),
-            smart_orders: None,
-            limit_orders: None
-        });
-    } else {
-        return Ok(OrdersResponse {
-            address: address.clone(),
-            limit_orders: None,
-            smart_orders: None,
-            grid_orders: None
-        });
     }
-    
-    
-}
 
+    Ok(OrdersResponse { address, orders })
+}
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
     let version = get_contract_version(deps.storage)?;
     if version.contract != CONTRACT_NAME {
         return Err(ContractError::CannotMigrate {
             previous_contract: version.contract,
         });
     }
-    Ok(Response::default())
+
+    // Config and orders of the first release are converted to the current shape
+    legacy::migrate_config(deps.storage, msg.fee_recipient)?;
+    let converted = legacy::migrate_orders(deps.storage)?;
+
+    // Orders saved before the indexes existed are saved again to index them
+    reindex_orders(deps.storage, limit_orders())?;
+    reindex_orders(deps.storage, smart_orders())?;
+    reindex_orders(deps.storage, grid_orders())?;
+    reindex_orders(deps.storage, dca_orders())?;
+    reindex_orders(deps.storage, twap_orders())?;
+    reindex_orders(deps.storage, bracket_orders())?;
+
+    // Finished orders were left in the active id lists, move them to the archive
+    let mut archived = 0u64;
+    for order_type in OrderType::ALL {
+        archived += ordergroup::archive_finished_orders(deps.storage, order_type)?;
+    }
+
+    // Withdraw only leaves the escrow in the contract, it must cover every active order
+    ordergroup::rebuild_escrow(deps.storage)?;
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("converted_orders", converted.to_string())
+        .add_attribute("archived_orders", archived.to_string()))
 }
 
+
+fn reindex_orders<'a, T: Serialize + DeserializeOwned + Clone>(
+    storage: &mut dyn Storage,
+    orders: IndexedMap<'a, (Addr, u64), T, OrderIndexes<'a, T>>
+) -> StdResult<()> {
+    let all:StdResult<Vec<_>> = orders
+        .range(storage, None, None, Order::Ascending)
+        .collect();
+    for (key, order) in all? {
+        orders.save(storage, key, &order)?;
+    }
+    Ok(())
+}
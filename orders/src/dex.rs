@@ -0,0 +1,339 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    to_binary, Addr, Coin, CosmosMsg, Decimal, QuerierWrapper, QueryRequest, Uint128, WasmMsg, WasmQuery
+};
+use cw20::{Cw20ExecuteMsg, Denom};
+use crate::error::ContractError;
+use crate::msg::DexKind;
+
+use wasmswap::msg::{ExecuteMsg as WasmswapExecuteMsg, QueryMsg as WasmswapQueryMsg, Token1ForToken2PriceResponse, Token2ForToken1PriceResponse, InfoResponse as WasmswapInfoResponse, TokenSelect};
+
+// Pool info, quoting and swap messages of one kind of pool contract
+pub trait DexAdapter {
+    // token1 and token2 of the pool
+    fn pool_denoms(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr
+    ) -> Result<(Denom, Denom), ContractError>;
+
+    // output of a swap of input_amount at the current pool price
+    fn quote(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr,
+        input_denom: &Denom,
+        input_is_token1: bool,
+        input_amount: Uint128
+    ) -> Result<Uint128, ContractError>;
+
+    // messages that swap input_amount and fail below min_output
+    fn swap_messages(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr,
+        input_denom: &Denom,
+        input_is_token1: bool,
+        input_amount: Uint128,
+        min_output: Uint128
+    ) -> Result<Vec<CosmosMsg>, ContractError>;
+}
+
+pub fn adapter(
+    dex: DexKind
+) -> &'static dyn DexAdapter {
+    match dex {
+        DexKind::Wasmswap => &Wasmswap,
+        DexKind::Astroport => &AstroportPair
+    }
+}
+
+/// Wasmswap ////////////////////////////////////////////////////////////////////////////////////////////
+pub struct Wasmswap;
+
+impl DexAdapter for Wasmswap {
+    fn pool_denoms(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr
+    ) -> Result<(Denom, Denom), ContractError> {
+        let pool_info_response: WasmswapInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: pool_address.clone().into(),
+            msg: to_binary(&WasmswapQueryMsg::Info {})?,
+        }))?;
+        Ok((pool_info_response.token1_denom, pool_info_response.token2_denom))
+    }
+
+    fn quote(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr,
+        _input_denom: &Denom,
+        input_is_token1: bool,
+        input_amount: Uint128
+    ) -> Result<Uint128, ContractError> {
+        if input_is_token1 {
+            let token2_price_response: Token1ForToken2PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: pool_address.clone().into(),
+                msg: to_binary(&WasmswapQueryMsg::Token1ForToken2Price {
+                    token1_amount: input_amount
+                })?,
+            }))?;
+            return Ok(token2_price_response.token2_amount);
+        }
+
+        let token1_price_response: Token2ForToken1PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: pool_address.clone().into(),
+            msg: to_binary(&WasmswapQueryMsg::Token2ForToken1Price {
+                token2_amount: input_amount
+            })?,
+        }))?;
+        Ok(token1_price_response.token1_amount)
+    }
+
+    fn swap_messages(
+        &self,
+        _querier: QuerierWrapper,
+        pool_address: &Addr,
+        input_denom: &Denom,
+        input_is_token1: bool,
+        input_amount: Uint128,
+        min_output: Uint128
+    ) -> Result<Vec<CosmosMsg>, ContractError> {
+        let input_token = if input_is_token1 { TokenSelect::Token1 } else { TokenSelect::Token2 };
+        let swap_msg = to_binary(&WasmswapExecuteMsg::Swap {
+            input_token,
+            input_amount,
+            min_output,
+            expiration: None
+        })?;
+
+        let mut messages: Vec<CosmosMsg> = vec![];
+        match input_denom.clone() {
+            Denom::Native(native_str) => {
+                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: pool_address.clone().into(),
+                    funds: vec![Coin {
+                        denom: native_str,
+                        amount: input_amount
+                    }],
+                    msg: swap_msg,
+                }));
+            },
+            Denom::Cw20(cw20_address) => {
+                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: cw20_address.into(),
+                    funds: vec![],
+                    msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
+                        spender: pool_address.clone().into(),
+                        amount: input_amount,
+                        expires: None
+                    })?,
+                }));
+                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: pool_address.clone().into(),
+                    funds: vec![],
+                    msg: swap_msg,
+                }));
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// Astroport / Terraswap pair ///////////////////////////////////////////////////////////////////////////
+// Only the parts of the pair interface the orders use
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    Token { contract_addr: Addr },
+    NativeToken { denom: String }
+}
+
+impl From<Denom> for AssetInfo {
+    fn from(denom: Denom) -> Self {
+        match denom {
+            Denom::Native(denom) => AssetInfo::NativeToken { denom },
+            Denom::Cw20(contract_addr) => AssetInfo::Token { contract_addr }
+        }
+    }
+}
+
+impl From<AssetInfo> for Denom {
+    fn from(info: AssetInfo) -> Self {
+        match info {
+            AssetInfo::NativeToken { denom } => Denom::Native(denom),
+            AssetInfo::Token { contract_addr } => Denom::Cw20(contract_addr)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairQueryMsg {
+    Pair {},
+    Simulation { offer_asset: Asset }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairInfo {
+    pub asset_infos: Vec<AssetInfo>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    pub return_amount: Uint128,
+    pub spread_amount: Uint128,
+    pub commission_amount: Uint128
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairExecuteMsg {
+    Swap {
+        offer_asset: Asset,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>
+    }
+}
+
+// sent along with the tokens through cw20 Send
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairCw20HookMsg {
+    Swap {
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>
+    }
+}
+
+pub struct AstroportPair;
+
+fn simulate(
+    querier: QuerierWrapper,
+    pool_address: &Addr,
+    input_denom: &Denom,
+    input_amount: Uint128
+) -> Result<SimulationResponse, ContractError> {
+    Ok(querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pool_address.clone().into(),
+        msg: to_binary(&PairQueryMsg::Simulation {
+            offer_asset: Asset {
+                info: input_denom.clone().into(),
+                amount: input_amount
+            }
+        })?,
+    }))?)
+}
+
+// amount * numerator / denominator rounded up
+fn ceil_ratio(amount: Uint128, numerator: Uint128, denominator: Uint128) -> Uint128 {
+    let floor = amount.multiply_ratio(numerator, denominator);
+    if floor.full_mul(denominator) < amount.full_mul(numerator) {
+        return floor + Uint128::from(1u128);
+    }
+    floor
+}
+
+impl DexAdapter for AstroportPair {
+    fn pool_denoms(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr
+    ) -> Result<(Denom, Denom), ContractError> {
+        let pair_info: PairInfo = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: pool_address.clone().into(),
+            msg: to_binary(&PairQueryMsg::Pair {})?,
+        }))?;
+        let mut asset_infos = pair_info.asset_infos.into_iter();
+        match (asset_infos.next(), asset_infos.next()) {
+            (Some(token1_info), Some(token2_info)) => Ok((token1_info.into(), token2_info.into())),
+            _ => Err(ContractError::PoolAndTokenMismatch {})
+        }
+    }
+
+    fn quote(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr,
+        input_denom: &Denom,
+        _input_is_token1: bool,
+        input_amount: Uint128
+    ) -> Result<Uint128, ContractError> {
+        Ok(simulate(querier, pool_address, input_denom, input_amount)?.return_amount)
+    }
+
+    // The pair has no minimum output, belief_price with no spread allowed does the same.
+    // The pair checks the output before its commission is taken, so the belief price is raised by the commission
+    fn swap_messages(
+        &self,
+        querier: QuerierWrapper,
+        pool_address: &Addr,
+        input_denom: &Denom,
+        _input_is_token1: bool,
+        input_amount: Uint128,
+        min_output: Uint128
+    ) -> Result<Vec<CosmosMsg>, ContractError> {
+        // without a minimum any output is taken, up to the widest spread the pair accepts instead of its 0.5% default
+        let mut belief_price = None;
+        let mut max_spread = Some(Decimal::percent(50));
+        if !min_output.is_zero() {
+            let simulation = simulate(querier, pool_address, input_denom, input_amount)?;
+            let mut min_output_before_commission = min_output;
+            if !simulation.return_amount.is_zero() {
+                min_output_before_commission = ceil_ratio(min_output, simulation.return_amount + simulation.commission_amount, simulation.return_amount);
+            }
+            belief_price = Some(Decimal::from_ratio(input_amount, min_output_before_commission));
+            max_spread = Some(Decimal::zero());
+        }
+
+        let mut messages: Vec<CosmosMsg> = vec![];
+        match input_denom.clone() {
+            Denom::Native(native_str) => {
+                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: pool_address.clone().into(),
+                    funds: vec![Coin {
+                        denom: native_str.clone(),
+                        amount: input_amount
+                    }],
+                    msg: to_binary(&PairExecuteMsg::Swap {
+                        offer_asset: Asset {
+                            info: AssetInfo::NativeToken { denom: native_str },
+                            amount: input_amount
+                        },
+                        belief_price,
+                        max_spread,
+                        to: None
+                    })?,
+                }));
+            },
+            Denom::Cw20(cw20_address) => {
+                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: cw20_address.into(),
+                    funds: vec![],
+                    msg: to_binary(&Cw20ExecuteMsg::Send {
+                        contract: pool_address.clone().into(),
+                        amount: input_amount,
+                        msg: to_binary(&PairCw20HookMsg::Swap {
+                            belief_price,
+                            max_spread,
+                            to: None
+                        })?
+                    })?,
+                }));
+            }
+        }
+        Ok(messages)
+    }
+}
@@ -1,4 +1,5 @@
 use cosmwasm_std::{StdError, Uint128};
+use crate::msg::OrderType;
 use hex::FromHexError;
 use thiserror::Error;
 
@@ -25,6 +26,33 @@ pub enum ContractError {
     #[error("Insufficient amount for Grid order")]
     InsufficientAmountForGridOrder {},
 
+    #[error("Grid needs at least one grid pair")]
+    InvalidGridPairs {},
+
+    #[error("Grid needs either a price range percentage below 100 or both lower and upper prices")]
+    InvalidGridRange {},
+
+    #[error("Grid bounds must be around the current price")]
+    GridPriceOutOfBounds {},
+
+    #[error("Grid levels are too close to each other")]
+    GridSpacingTooSmall {},
+
+    #[error("Insufficient amount for DCA order")]
+    InsufficientAmountForDcaOrder {},
+
+    #[error("Insufficient amount for TWAP order")]
+    InsufficientAmountForTwapOrder {},
+
+    #[error("Amount exceeds what the order holds")]
+    InsufficientOrderAmount {},
+
+    #[error("Not enough left for the DCA or grid levels still armed")]
+    LadderUnderfunded {},
+
+    #[error("{order_type} orders do not support this")]
+    UnsupportedOrderType { order_type: OrderType },
+
     #[error("OrderNotExist")]
     OrderNotExist {},
 
@@ -43,16 +71,46 @@ pub enum ContractError {
     #[error("The pool does not contain the input token")]
     PoolAndTokenMismatch {},
 
+    #[error("Slippage tolerance must not exceed 10000 bps")]
+    InvalidSlippage {},
+
+    #[error("Swap output would be below the order's price less the slippage tolerance")]
+    SlippageExceeded {},
+
+    #[error("Expiration must be in the future")]
+    InvalidExpiration {},
+
+    #[error("Pool is not registered")]
+    PoolNotRegistered {},
+
+    #[error("Pool is already registered")]
+    PoolAlreadyRegistered {},
+
+    #[error("Pool is disabled")]
+    PoolDisabled {},
+
     #[error("InvalidInput")]
     InvalidInput {},
 
     #[error("Send some coins to create an order")]
     EmptyBalance {},
 
+    #[error("Nothing to withdraw above the escrowed amount")]
+    NothingToWithdraw {},
+
+    #[error("No pending swap for the reply")]
+    NoPendingSwap {},
+
+    #[error("Unknown reply id {id}")]
+    UnknownReplyId { id: u64 },
+
     #[error("Debug {value}")]
     DebugValue { value: Uint128},
 
     #[error("Cannot migrate from different contract type: {previous_contract}")]
     CannotMigrate { previous_contract: String },
 
+    #[error("A fee recipient is required to migrate a config without one")]
+    MissingFeeRecipient {},
+
 }
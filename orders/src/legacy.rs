@@ -0,0 +1,285 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use cosmwasm_std::{Addr, Order, StdResult, Storage, Uint128};
+use cw20::Denom;
+use cw_storage_plus::{IndexedMap, Item, Map};
+
+use crate::error::ContractError;
+use crate::msg::{
+    DexKind, LimitMsg, LimitConfig, LimitPhase, SmartMsg, SmartConfig, GridMsg, GridConfig, GridLevel, GridLevelState, OrderStats
+};
+use crate::state::{
+    Config, CONFIG, CONFIG_KEY, OrderIndexes, LIMIT_ORDERS_COUNT, SMART_ORDERS_COUNT, GRID_ORDERS_COUNT, limit_orders, smart_orders, grid_orders
+};
+use crate::ordergroup;
+use crate::util;
+
+// Records of the first release, stored under the same keys as the current ones
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyConfig {
+    pub owner: Addr,
+    pub enabled: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyLimitMsg {
+    pub token1_denom: Denom,
+    pub pool_address: Addr,
+    pub take_profit_percentage: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyLimitConfig {
+    pub msg: LegacyLimitMsg,
+    pub token2_denom: Denom,
+    pub initial_token1_amount: Uint128,
+    pub token1_amount: Uint128,
+    pub token2_amount: Uint128,
+    pub avg_buy_price: Uint128,
+    pub target_buy_price: Uint128,
+    pub finished: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacySmartMsg {
+    pub token1_denom: Denom,
+    pub pool_address: Addr,
+    pub take_profit_percentage: u64,
+    pub initial_token1_amount: Uint128,
+    pub num_dca_orders: u64,
+    pub dca_step: u64,
+    pub dca_step_multiplier: u64,
+    pub dca_order_size: Uint128,
+    pub dca_order_size_multiplier: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacySmartConfig {
+    pub msg: LegacySmartMsg,
+    pub token2_denom: Denom,
+    pub token1_amount: Uint128,
+    pub token2_amount: Uint128,
+    pub avg_buy_price: Uint128,
+    pub target_buy_price: Uint128,
+    pub finished: bool,
+    pub dca_prices: Vec<Uint128>,
+    pub dca_amounts: Vec<Uint128>,
+    pub current_dca_point: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyGridMsg {
+    pub token1_denom: Denom,
+    pub pool_address: Addr,
+    pub total_amount: Uint128,
+    pub num_grid_pairs: u64,
+    pub price_range_percentage: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyGridConfig {
+    pub msg: LegacyGridMsg,
+    pub token2_denom: Denom,
+    pub buy_prices: Vec<Uint128>, // descending from the start price
+    pub sell_prices: Vec<Uint128>, // ascending from the start price
+    pub order_amount: Uint128,
+    pub finished: bool,
+    pub buy_step: u64, // buy levels filled so far, from the first one
+    pub sell_step: u64, // sell levels filled so far, from the first one
+    pub token1_amount: Uint128,
+    pub token2_amount: Uint128
+}
+
+const LEGACY_CONFIG: Item<LegacyConfig> = Item::new(CONFIG_KEY);
+
+// A config without the fee settings is given the fee recipient of the migration
+// the orders were opened without any fee, so both fees start at zero until the owner sets them
+pub fn migrate_config(
+    storage: &mut dyn Storage,
+    fee_recipient: Option<Addr>
+) -> Result<(), ContractError> {
+    let mut config = match CONFIG.load(storage) {
+        Ok(config) => config,
+        Err(_) => {
+            let legacy = LEGACY_CONFIG.load(storage)?;
+            Config {
+                owner: legacy.owner,
+                enabled: legacy.enabled,
+                max_slippage_bps: util::DEFAULT_MAX_SLIPPAGE_BPS,
+                keeper_fee_bps: 0u64,
+                performance_fee_bps: 0u64,
+                fee_recipient: fee_recipient.clone().ok_or(ContractError::MissingFeeRecipient {})?
+            }
+        }
+    };
+    if let Some(fee_recipient) = fee_recipient {
+        config.fee_recipient = fee_recipient;
+    }
+    CONFIG.save(storage, &config)?;
+    Ok(())
+}
+
+// Convert every limit, smart and grid order still in the legacy shape, returns how many were
+pub fn migrate_orders(storage: &mut dyn Storage) -> StdResult<u64> {
+    let mut converted = 0u64;
+    converted += convert_orders(storage, LIMIT_ORDERS_COUNT, Map::new("limit_orders"), limit_orders(), convert_limit)?;
+    converted += convert_orders(storage, SMART_ORDERS_COUNT, Map::new("smart_orders"), smart_orders(), convert_smart)?;
+    converted += convert_orders(storage, GRID_ORDERS_COUNT, Map::new("grid_orders"), grid_orders(), convert_grid)?;
+    Ok(converted)
+}
+
+fn convert_orders<'a, L, T>(
+    storage: &mut dyn Storage,
+    count: Map<Addr, (Vec<u64>, u64)>,
+    legacy_orders: Map<(Addr, u64), L>,
+    orders: IndexedMap<'a, (Addr, u64), T, OrderIndexes<'a, T>>,
+    convert: fn(L) -> T
+) -> StdResult<u64>
+where
+    L: Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned + Clone
+{
+    let all:StdResult<Vec<_>> = count
+        .range(storage, None, None, Order::Ascending)
+        .collect();
+
+    let mut converted = 0u64;
+    for (address, (list, _max_number)) in all? {
+        for id in list {
+            let key = (address.clone(), id);
+            if orders.may_load(storage, key.clone()).is_ok() {
+                continue;
+            }
+            let legacy = legacy_orders.load(storage, key.clone())?;
+            // the indexed map reads the old value on save, the legacy one is removed first
+            legacy_orders.remove(storage, key.clone());
+            orders.save(storage, key, &convert(legacy))?;
+            converted += 1;
+        }
+    }
+    Ok(converted)
+}
+
+fn convert_limit(legacy: LegacyLimitConfig) -> LimitConfig {
+    LimitConfig {
+        msg: LimitMsg {
+            token1_denom: legacy.msg.token1_denom,
+            pool_address: legacy.msg.pool_address,
+            take_profit_percentage: legacy.msg.take_profit_percentage,
+            stop_loss_percentage: None,
+            trail_percentage: None,
+            entry_price: None,
+            max_slippage_bps: None,
+            expires_at: None
+        },
+        phase: LimitPhase::TakeProfit,
+        token2_denom: legacy.token2_denom,
+        dex: DexKind::Wasmswap,
+        initial_token1_amount: legacy.initial_token1_amount,
+        token1_amount: legacy.token1_amount,
+        token2_amount: legacy.token2_amount,
+        avg_buy_price: legacy.avg_buy_price,
+        target_buy_price: legacy.target_buy_price,
+        stop_loss_price: None,
+        peak_price: None,
+        trail_trigger_price: None,
+        finished: legacy.finished,
+        stats: OrderStats::default()
+    }
+}
+
+// The legacy token1_amount still counts the refund sent on start, the ladder left is recomputed
+fn convert_smart(legacy: LegacySmartConfig) -> SmartConfig {
+    let msg = SmartMsg {
+        token1_denom: legacy.msg.token1_denom,
+        pool_address: legacy.msg.pool_address,
+        take_profit_percentage: legacy.msg.take_profit_percentage,
+        stop_loss_percentage: None,
+        initial_token1_amount: legacy.msg.initial_token1_amount,
+        num_dca_orders: legacy.msg.num_dca_orders,
+        dca_step: legacy.msg.dca_step,
+        dca_step_multiplier: legacy.msg.dca_step_multiplier,
+        dca_order_size: legacy.msg.dca_order_size,
+        dca_order_size_multiplier: legacy.msg.dca_order_size_multiplier,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let deposit_amount = ordergroup::smart_deposit_amount(&msg);
+    let mut token1_amount = legacy.token1_amount;
+    if !legacy.finished {
+        let spent: Uint128 = legacy.dca_amounts.iter().take(legacy.current_dca_point as usize).sum();
+        token1_amount = deposit_amount.saturating_sub(msg.initial_token1_amount).saturating_sub(spent);
+    }
+
+    SmartConfig {
+        msg,
+        token2_denom: legacy.token2_denom,
+        dex: DexKind::Wasmswap,
+        deposit_amount,
+        token1_amount,
+        keeper_tip: Uint128::zero(),
+        token2_amount: legacy.token2_amount,
+        avg_buy_price: legacy.avg_buy_price,
+        target_buy_price: legacy.target_buy_price,
+        stop_loss_price: None,
+        finished: legacy.finished,
+        stats: OrderStats::default(),
+        dca_prices: legacy.dca_prices,
+        dca_amounts: legacy.dca_amounts,
+        current_dca_point: legacy.current_dca_point
+    }
+}
+
+// The buy and sell lists become one ascending ladder, the levels already filled are left idle
+fn convert_grid(legacy: LegacyGridConfig) -> GridConfig {
+    let mut levels: Vec<GridLevel> = vec![];
+    for (i, price) in legacy.buy_prices.iter().enumerate().rev() {
+        levels.push(GridLevel {
+            price: *price,
+            state: if (i as u64) < legacy.buy_step { GridLevelState::Idle } else { GridLevelState::Buy },
+            rearmed: false
+        });
+    }
+    // the start price is not stored, it sits halfway between the first buy and sell levels
+    if let (Some(buy_price), Some(sell_price)) = (legacy.buy_prices.first(), legacy.sell_prices.first()) {
+        levels.push(GridLevel {
+            price: (*buy_price + *sell_price) / Uint128::from(2u128),
+            state: GridLevelState::Idle,
+            rearmed: false
+        });
+    }
+    for (i, price) in legacy.sell_prices.iter().enumerate() {
+        levels.push(GridLevel {
+            price: *price,
+            state: if (i as u64) < legacy.sell_step { GridLevelState::Idle } else { GridLevelState::Sell },
+            rearmed: false
+        });
+    }
+
+    GridConfig {
+        msg: GridMsg {
+            token1_denom: legacy.msg.token1_denom,
+            pool_address: legacy.msg.pool_address,
+            total_amount: legacy.msg.total_amount,
+            num_grid_pairs: legacy.msg.num_grid_pairs,
+            price_range_percentage: Some(legacy.msg.price_range_percentage),
+            lower_price: None,
+            upper_price: None,
+            spacing: None,
+            max_slippage_bps: None,
+            expires_at: None
+        },
+        token2_denom: legacy.token2_denom,
+        dex: DexKind::Wasmswap,
+        levels,
+        order_amount: legacy.order_amount,
+        finished: legacy.finished,
+        stats: OrderStats::default(),
+        round_trips: 0u64,
+        token1_amount: legacy.token1_amount,
+        token2_amount: legacy.token2_amount
+    }
+}
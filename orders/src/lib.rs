@@ -1,5 +1,7 @@
 pub mod contract;
+pub mod dex;
 mod error;
+mod legacy;
 pub mod msg;
 pub mod state;
 
@@ -3,9 +3,65 @@ use serde::{Deserialize, Serialize};
 use cw20::{Cw20ReceiveMsg};
 use cosmwasm_std::{Uint128, Addr};
 use cw20::{Denom};
+use std::fmt;
 
-#[derive(Serialize, Deserialize, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
+    pub treasury_address: Addr, // default recipient of the performance fee
+}
+
+/// Order Types //////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    Limit,
+    Smart,
+    Grid,
+    Dca,
+    Twap,
+    Bracket
+}
+
+impl OrderType {
+    pub const ALL: [OrderType; 6] = [OrderType::Limit, OrderType::Smart, OrderType::Grid, OrderType::Dca, OrderType::Twap, OrderType::Bracket];
+
+    // Number the order type is kept under in storage keys
+    pub fn key(&self) -> u64 {
+        match self {
+            OrderType::Limit => 0u64,
+            OrderType::Smart => 1u64,
+            OrderType::Grid => 2u64,
+            OrderType::Dca => 3u64,
+            OrderType::Twap => 4u64,
+            OrderType::Bracket => 5u64
+        }
+    }
+}
+
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OrderType::Limit => "limit",
+            OrderType::Smart => "smart",
+            OrderType::Grid => "grid",
+            OrderType::Dca => "dca",
+            OrderType::Twap => "twap",
+            OrderType::Bracket => "bracket"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The stored config of an order, tagged with its type
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderConfig {
+    Limit(LimitConfig),
+    Smart(SmartConfig),
+    Grid(GridConfig),
+    Dca(DcaConfig),
+    Twap(TwapConfig),
+    Bracket(BracketConfig)
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -17,23 +73,64 @@ pub enum ExecuteMsg {
     UpdateEnabled {
         enabled: bool
     },
+    UpdateMaxSlippage {
+        max_slippage_bps: u64
+    },
+    UpdateKeeperFee {
+        keeper_fee_bps: u64
+    },
+    UpdatePerformanceFee {
+        performance_fee_bps: u64,
+        fee_recipient: Addr
+    },
     Receive(Cw20ReceiveMsg),
 
     Stop {
-        order_type: u64,
+        order_type: OrderType,
         id: u64
     },
     Sync {
-        order_type: u64,
+        order_type: OrderType,
         address: Option<Addr>,
         id: u64
     },
+    SyncBatch {
+        orders: Vec<(OrderType, Addr, u64)> // (order_type, address, id)
+    },
 
     StartLimit(LimitMsg),
     StartSmart(SmartMsg),
     StartGrid(GridMsg),
+    StartDca(DcaMsg),
+    StartTwap(TwapMsg),
+    StartBracket(BracketMsg),
+    IncreaseOrder {
+        order_type: OrderType,
+        id: u64
+    },
+    AmendOrder {
+        id: u64,
+        amendment: OrderAmendment
+    },
+    DecreaseOrder {
+        order_type: OrderType,
+        id: u64,
+        amount: Uint128
+    },
     Withdraw {
         denom: Denom
+    },
+
+    AddPool {
+        pool_address: Addr,
+        dex: Option<DexKind> // wasmswap if not set
+    },
+    RemovePool {
+        pool_address: Addr
+    },
+    UpdatePoolEnabled {
+        pool_address: Addr,
+        enabled: bool
     }
 }
 
@@ -54,9 +151,7 @@ pub struct OrderForAddressIdsResponse {
 pub struct OrderResponse {
     pub address: Addr,
     pub id: u64,
-    pub limit_order: Option<LimitConfig>,
-    pub smart_order: Option<SmartConfig>,
-    pub grid_order: Option<GridConfig>,
+    pub order: OrderConfig,
 }
 
 
@@ -65,29 +160,162 @@ pub struct OrderResponse {
 #[serde(rename_all = "snake_case")]
 pub struct OrdersResponse {
     pub address: Addr,
-    pub limit_orders: Option<Vec<LimitConfig>>,
-    pub smart_orders: Option<Vec<SmartConfig>>,
-    pub grid_orders: Option<Vec<GridConfig>>,
+    pub orders: Vec<OrderResponse>,
+}
+/// Pool Registry ///////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DexKind {
+    #[default]
+    Wasmswap, // Info, Token1ForToken2Price and Swap with TokenSelect
+    Astroport // Astroport and Terraswap style pairs: Pair, Simulation and Swap, through cw20 Send for tokens
+}
+
+impl fmt::Display for DexKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DexKind::Wasmswap => "wasmswap",
+            DexKind::Astroport => "astroport"
+        };
+        write!(f, "{}", name)
+    }
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolConfig {
+    pub pool_address: Addr,
+    pub token1_denom: Denom,
+    pub token2_denom: Denom,
+    #[serde(default)]
+    pub dex: DexKind, // pools registered before the adapters are wasmswap
+    pub enabled: bool // disabled pools accept no new orders, existing ones can still be stopped
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PoolsResponse {
+    pub pools: Vec<PoolConfig>,
+}
+
+/// Order Amendment /////////////////////////////////////////////////////////////////////////////////////
+// The fields each order type can change while active, fields not set are kept
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderAmendment {
+    Limit(LimitAmendment),
+    Smart(SmartAmendment),
+    Grid(GridAmendment)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LimitAmendment {
+    pub take_profit_percentage: Option<u64>,
+    pub stop_loss_percentage: Option<u64>,
+    pub trail_percentage: Option<u64>,
+    pub max_slippage_bps: Option<u64>,
+    pub expires_at: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SmartAmendment {
+    pub take_profit_percentage: Option<u64>,
+    pub stop_loss_percentage: Option<u64>,
+    pub dca_step: Option<u64>,
+    pub dca_step_multiplier: Option<u64>,
+    pub max_slippage_bps: Option<u64>,
+    pub expires_at: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct GridAmendment {
+    pub price_range_percentage: Option<u64>, // replaces the bounds
+    pub lower_price: Option<Uint128>, // replaces the percentage, given together with upper_price
+    pub upper_price: Option<Uint128>,
+    pub spacing: Option<GridSpacing>,
+    pub max_slippage_bps: Option<u64>,
+    pub expires_at: Option<u64>
+}
+
+/// Order History ///////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FillDirection {
+    Buy, // token1 swapped for token2
+    Sell // token2 swapped for token1
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderFill {
+    pub seq: u64,
+    pub timestamp: u64, // block time in seconds
+    pub direction: FillDirection,
+    pub amount_in: Uint128,
+    pub amount_out: Uint128,
+    pub price: Uint128 // token1 per token2, multiplied by 1000000
+}
+
+// Totals kept on every order, in token1
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct OrderStats {
+    pub realized_profit: Uint128, // returned to the sender above the deposit
+    pub realized_loss: Uint128, // deposit not returned to the sender
+    pub keeper_fees: Uint128,
+    pub performance_fees: Uint128
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct OrderFillsResponse {
+    pub fills: Vec<OrderFill>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct OrderListResponse {
+    pub orders: Vec<OrderResponse>,
+}
+
 /// Limit Order ///////////////////////////////////////////////////////////////////////////////////////////
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct LimitMsg {
     pub token1_denom: Denom, // {"cw20":"address"} or {"native":"ujuno"}
     pub pool_address: Addr, // pool address
-    pub take_profit_percentage: u64 // minimum advantage rate to sell
+    pub take_profit_percentage: u64, // minimum advantage rate to sell
+    pub stop_loss_percentage: Option<u64>, // percentage below the avg_buy_price at which it should close the position
+    pub trail_percentage: Option<u64>, // trailing take profit, sell when the price falls back this percentage from the peak
+    pub entry_price: Option<Uint128>, // limit buy, wait until the price is at or below this entry before buying
+    pub max_slippage_bps: Option<u64>, // slippage tolerance for each swap, contract default if not set
+    pub expires_at: Option<u64> // block time in seconds after which the order is closed on sync
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPhase {
+    Entry, // waiting for the entry price, holding token1
+    TakeProfit // holding token2, waiting to sell
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct LimitConfig {
     pub msg: LimitMsg,
+    pub phase: LimitPhase,
     pub token2_denom: Denom,
+    #[serde(default)]
+    pub dex: DexKind, // adapter of the pool, recorded when the order starts
     pub initial_token1_amount: Uint128,
     pub token1_amount: Uint128,
     pub token2_amount: Uint128,
     pub avg_buy_price: Uint128,
     pub target_buy_price: Uint128,
-    pub finished: bool
+    pub stop_loss_price: Option<Uint128>,
+    pub peak_price: Option<Uint128>, // highest price observed in trailing mode
+    pub trail_trigger_price: Option<Uint128>, // current trailing sell level
+    pub finished: bool,
+    pub stats: OrderStats
 }
 
 /// Smart Order ///////////////////////////////////////////////////////////////////////////////////////////
@@ -97,23 +325,33 @@ pub struct SmartMsg {
     pub token1_denom: Denom, // {"cw20":"address"} or {"native":"ujuno"}
     pub pool_address: Addr, // pool address
     pub take_profit_percentage: u64, // percentage above the average_purchase_price at which it should take profit
+    pub stop_loss_percentage: Option<u64>, // percentage below the average_purchase_price at which it should close the position
     pub initial_token1_amount: Uint128, // initial buy amount, not same as input amount
     pub num_dca_orders: u64, // number of orders created for double cost averaging
     pub dca_step: u64, // difference of the price drop between dca_orders
     pub dca_step_multiplier: u64, // multiplier of the dca_step
     pub dca_order_size: Uint128, // order size for each dca_order
-    pub dca_order_size_multiplier: u64 // multiplier of the dca_order_size
+    pub dca_order_size_multiplier: u64, // multiplier of the dca_order_size
+    pub max_slippage_bps: Option<u64>, // slippage tolerance for each swap, contract default if not set
+    pub expires_at: Option<u64> // block time in seconds after which the order is closed on sync
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct SmartConfig {
     pub msg: SmartMsg,
     pub token2_denom: Denom,
+    #[serde(default)]
+    pub dex: DexKind, // adapter of the pool, recorded when the order starts
+    pub deposit_amount: Uint128, // token1 kept for the order, after the refund
     pub token1_amount: Uint128,
+    #[serde(default)]
+    pub keeper_tip: Uint128, // token1 kept back from the last dca buy for the keeper, part of token1_amount until paid
     pub token2_amount: Uint128,
     pub avg_buy_price: Uint128,
     pub target_buy_price: Uint128,
+    pub stop_loss_price: Option<Uint128>,
     pub finished: bool,
+    pub stats: OrderStats,
     pub dca_prices: Vec<Uint128>,
     pub dca_amounts: Vec<Uint128>,
     pub current_dca_point: u64
@@ -128,29 +366,164 @@ pub struct GridMsg {
     pub pool_address: Addr, // pool address
     pub total_amount: Uint128, // total input amount
     pub num_grid_pairs: u64, // number of orders created for double cost averaging
-    pub price_range_percentage: u64, // -10%~10%
+    pub price_range_percentage: Option<u64>, // -10%~10%, not set when the bounds are given
+    pub lower_price: Option<Uint128>, // lowest buy level, given together with upper_price
+    pub upper_price: Option<Uint128>, // highest sell level, given together with lower_price
+    pub spacing: Option<GridSpacing>, // arithmetic if not set
+    pub max_slippage_bps: Option<u64>, // slippage tolerance for each swap, contract default if not set
+    pub expires_at: Option<u64> // block time in seconds after which the order is closed on sync
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GridSpacing {
+    Arithmetic, // the same price difference between two levels
+    Geometric // the same price ratio between two levels
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GridLevelState {
+    Idle, // nothing armed, the price sits next to this level
+    Buy, // buys when the price goes down to this level
+    Sell // sells when the price goes up to this level
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GridLevel {
+    pub price: Uint128,
+    pub state: GridLevelState,
+    pub rearmed: bool // armed by a fill on the neighbouring level, filling it completes a round trip
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct GridConfig {
     pub msg: GridMsg, 
     pub token2_denom: Denom,
-    pub buy_prices: Vec<Uint128>, // The case when the second token price goes down
-    pub sell_prices: Vec<Uint128>, // The case when the second token price goes up
+    #[serde(default)]
+    pub dex: DexKind, // adapter of the pool, recorded when the order starts
+    pub levels: Vec<GridLevel>, // ascending prices, buys below the start price and sells above it
     pub order_amount: Uint128,
     pub finished: bool,
-    pub buy_step: u64,
-    pub sell_step: u64,
+    pub stats: OrderStats,
+    pub round_trips: u64,
     pub token1_amount: Uint128,
     pub token2_amount: Uint128
 }
 
+
+/// DCA Order ////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DcaMsg {
+    pub token1_denom: Denom, // {"cw20":"address"} or {"native":"ujuno"}
+    pub pool_address: Addr, // pool address
+    pub round_amount: Uint128, // token1 amount swapped each round
+    pub interval: u64, // seconds between two rounds
+    pub num_rounds: u64, // number of rounds, the first one runs when the order starts
+    pub max_slippage_bps: Option<u64>, // slippage tolerance for each swap, contract default if not set
+    pub expires_at: Option<u64> // block time in seconds after which the order is closed on sync
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DcaConfig {
+    pub msg: DcaMsg,
+    pub token2_denom: Denom,
+    #[serde(default)]
+    pub dex: DexKind, // adapter of the pool, recorded when the order starts
+    pub token1_amount: Uint128, // token1 left for the next rounds
+    #[serde(default)]
+    pub keeper_tip: Uint128, // token1 kept back at the last sync for the keeper, part of token1_amount until paid
+    pub token2_amount: Uint128, // token2 bought so far
+    pub rounds_executed: u64,
+    pub last_execution: u64, // block time in seconds the last executed round was due
+    pub finished: bool,
+    pub stats: OrderStats
+}
+
+
+/// TWAP Order ///////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct TwapMsg {
+    pub token1_denom: Denom, // {"cw20":"address"} or {"native":"ujuno"}
+    pub pool_address: Addr, // pool address
+    pub total_amount: Uint128, // total token1 amount to sell
+    pub num_slices: u64, // number of swaps the total amount is split into
+    pub interval: u64, // minimum seconds between two slices
+    pub min_price: Uint128, // minimum token2 received per token1 sold, multiplied by 1000000
+    pub max_slippage_bps: Option<u64>, // slippage tolerance for each swap, contract default if not set
+    pub expires_at: Option<u64> // block time in seconds after which the order is closed on sync
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapConfig {
+    pub msg: TwapMsg,
+    pub token2_denom: Denom,
+    #[serde(default)]
+    pub dex: DexKind, // adapter of the pool, recorded when the order starts
+    pub slice_amount: Uint128,
+    pub token1_amount: Uint128, // token1 not sold yet
+    #[serde(default)]
+    pub keeper_tip: Uint128, // token1 kept back from the last slice for the keeper, part of token1_amount until paid
+    pub token2_amount: Uint128, // token2 received so far
+    pub slices_filled: u64,
+    pub avg_price: Uint128, // average token2 received per token1 sold, multiplied by 1000000
+    pub last_execution: u64, // block time in seconds of the last slice
+    pub finished: bool,
+    pub stats: OrderStats
+}
+
+
+/// Bracket Order ////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct BracketMsg {
+    pub token1_denom: Denom, // {"cw20":"address"} or {"native":"ujuno"}
+    pub pool_address: Addr, // pool address
+    pub take_profit_percentage: u64, // take profit this percentage above the entry price
+    pub stop_loss_percentage: u64, // stop loss this percentage below the entry price
+    pub max_slippage_bps: Option<u64>, // slippage tolerance for each swap, contract default if not set
+    pub expires_at: Option<u64> // block time in seconds after which the order is closed on sync
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BracketExit {
+    TakeProfit,
+    StopLoss
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BracketConfig {
+    pub msg: BracketMsg,
+    pub token2_denom: Denom,
+    #[serde(default)]
+    pub dex: DexKind, // adapter of the pool, recorded when the order starts
+    pub initial_token1_amount: Uint128,
+    pub token1_amount: Uint128,
+    pub token2_amount: Uint128,
+    pub entry_price: Uint128,
+    pub take_profit_price: Uint128,
+    pub stop_loss_price: Uint128,
+    pub exit: Option<BracketExit>, // the exit that closed the position, none when stopped or expired
+    pub finished: bool,
+    pub stats: OrderStats
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ReceiveMsg {
     Limit(LimitMsg),
     Smart(SmartMsg),
-    Grid(GridMsg)
+    Grid(GridMsg),
+    Dca(DcaMsg),
+    Twap(TwapMsg),
+    Bracket(BracketMsg),
+    IncreaseOrder {
+        order_type: OrderType,
+        id: u64
+    }
     
 }
 
@@ -160,13 +533,55 @@ pub enum QueryMsg {
     Config {},
 
     OrderAddresses {
-        order_type: u64,
+        order_type: OrderType,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    OrderForAddressIds {order_type: OrderType, address: Addr},
+    Order {order_type: OrderType, address: Addr, id: u64},
+    // Every order type of the address if order_type is not set
+    Orders {order_type: Option<OrderType>, address: Addr},
+    Escrow {denom: Denom},
+    FeesCollected {denom: Denom},
+    Pools {
         start_after: Option<String>,
         limit: Option<u32>,
     },
-    OrderForAddressIds {order_type: u64, address: Addr},
-    Order {order_type: u64, address: Addr, id: u64},
-    Orders {order_type: u64, address: Addr},
+    SimulateLimit {msg: LimitMsg, amount: Uint128},
+    SimulateSmart {msg: SmartMsg, amount: Uint128},
+    SimulateGrid {msg: GridMsg, amount: Uint128},
+    OrderFills {
+        order_type: OrderType,
+        address: Addr,
+        id: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // Orders of every address, paginated by (address, id)
+    OrdersByPool {
+        order_type: OrderType,
+        pool_address: Addr,
+        start_after: Option<(Addr, u64)>,
+        limit: Option<u32>,
+    },
+    OrdersByDenom {
+        order_type: OrderType,
+        token1_denom: Denom,
+        start_after: Option<(Addr, u64)>,
+        limit: Option<u32>,
+    },
+    ActiveOrders {
+        order_type: OrderType,
+        start_after: Option<(Addr, u64)>,
+        limit: Option<u32>,
+    },
+    // Finished orders, moved out of the active ones once settled
+    ArchivedOrders {
+        order_type: OrderType,
+        address: Addr,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 
@@ -176,8 +591,61 @@ pub enum QueryMsg {
 #[serde(rename_all = "snake_case")]
 pub struct ConfigResponse {
     pub owner: Addr,
+    pub max_slippage_bps: u64,
+    pub keeper_fee_bps: u64,
+    pub performance_fee_bps: u64,
+    pub fee_recipient: Addr,
+}
+
+// What starting the order with `amount` of token1 would do, nothing is stored
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SimulateLimitResponse {
+    pub deposit_amount: Uint128, // kept by the order
+    pub refund_amount: Uint128, // sent back on start
+    pub swap_amount: Uint128, // token1 swapped on start, zero for a limit buy
+    pub swap_output: Uint128, // token2 quoted for the initial swap
+    pub limit_order: LimitConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SimulateSmartResponse {
+    pub deposit_amount: Uint128,
+    pub refund_amount: Uint128,
+    pub swap_amount: Uint128,
+    pub swap_output: Uint128,
+    pub smart_order: SmartConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SimulateGridResponse {
+    pub deposit_amount: Uint128,
+    pub refund_amount: Uint128,
+    pub swap_amount: Uint128,
+    pub swap_output: Uint128,
+    pub grid_order: GridConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct EscrowResponse {
+    pub denom: Denom,
+    pub balance: Uint128, // contract balance
+    pub escrowed: Uint128, // held for active orders
+    pub surplus: Uint128, // withdrawable by the owner
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct FeesCollectedResponse {
+    pub denom: Denom,
+    pub amount: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct MigrateMsg {}
+pub struct MigrateMsg {
+    pub fee_recipient: Option<Addr> // required when the stored config has none, replaces it otherwise
+}
 
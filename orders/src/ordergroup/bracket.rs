@@ -0,0 +1,213 @@
+use cosmwasm_std::{
+    DepsMut, Env, Response, Uint128, Addr
+};
+use cw20::{Balance, Denom};
+use crate::error::ContractError;
+use crate::msg::{
+    OrderType, DexKind, BracketMsg, BracketConfig, BracketExit, OrderStats
+};
+use crate::state::{
+    PendingSwap, bracket_orders, SwapKind
+};
+
+use crate::ordergroup::{
+    archive_order, start_order, sync_order, Transfer, OrderStrategy, OrderTerms, Quote, Transition
+};
+use crate::util;
+
+pub fn execute_start_bracket(
+    deps: DepsMut,
+    env: Env,
+    msg: BracketMsg,
+    balance: Balance,
+    address: Addr,
+) -> Result<Response, ContractError> {
+    start_order::<BracketConfig>(deps, env, msg, balance, address)
+}
+
+// Take profit and stop loss levels around the entry price
+fn set_exits(
+    bracket_config: &mut BracketConfig,
+    entry_price: Uint128
+) -> Result<(), ContractError> {
+    bracket_config.entry_price = entry_price;
+    bracket_config.take_profit_price = entry_price * (util::multiple() + Uint128::from(bracket_config.msg.take_profit_percentage)) / util::multiple();
+    bracket_config.stop_loss_price = util::get_stop_loss_price(entry_price, Some(bracket_config.msg.stop_loss_percentage))?.unwrap_or_default();
+    Ok(())
+}
+
+impl BracketConfig {
+    // Whichever exit triggers first closes the position, the other one is dropped
+    fn check_exits(&mut self, quote: &dyn Quote) -> Result<(), ContractError> {
+        let current_buy_price = self.initial_token1_amount * util::decimal() / quote.buy(self.initial_token1_amount)?;
+        if current_buy_price > self.take_profit_price {
+            self.exit = Some(BracketExit::TakeProfit);
+        } else if current_buy_price < self.stop_loss_price {
+            self.exit = Some(BracketExit::StopLoss);
+        }
+        Ok(())
+    }
+
+    // the position is sold at the current price, the caller adds the take profit limit
+    fn close(&mut self) -> Transition {
+        self.finished = true;
+        let action = match self.exit {
+            Some(BracketExit::TakeProfit) => "take_profit",
+            Some(BracketExit::StopLoss) => "stop_loss",
+            None => "success"
+        };
+        Transition::new(action).swap(SwapKind::Finish, self.token2_amount)
+    }
+}
+
+impl OrderStrategy for BracketConfig {
+    type Msg = BracketMsg;
+    const ORDER_TYPE: OrderType = OrderType::Bracket;
+
+    fn terms(msg: &BracketMsg) -> OrderTerms {
+        OrderTerms {
+            token1_denom: msg.token1_denom.clone(),
+            pool_address: msg.pool_address.clone(),
+            max_slippage_bps: msg.max_slippage_bps,
+            expires_at: msg.expires_at
+        }
+    }
+
+    fn msg(&self) -> &BracketMsg {
+        &self.msg
+    }
+
+    fn token2_denom(&self) -> Denom {
+        self.token2_denom.clone()
+    }
+
+    fn dex(&self) -> DexKind {
+        self.dex
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // The position is bought at once, the reply moves the exits to the price actually paid
+    fn on_start(
+        msg: BracketMsg,
+        token1_amount: Uint128,
+        token2_denom: Denom,
+        dex: DexKind,
+        quote: &dyn Quote,
+        _now: u64
+    ) -> Result<(Self, Transition), ContractError> {
+        if msg.take_profit_percentage == 0u64 || msg.stop_loss_percentage == 0u64 {
+            return Err(ContractError::InvalidInput {});
+        }
+
+        let token2_amount = quote.buy(token1_amount)?;
+        let mut bracket_config = BracketConfig {
+            msg,
+            token2_denom,
+            dex,
+            initial_token1_amount: token1_amount,
+            token1_amount: Uint128::zero(),
+            token2_amount,
+            entry_price: Uint128::zero(),
+            take_profit_price: Uint128::zero(),
+            stop_loss_price: Uint128::zero(),
+            exit: None,
+            finished: false,
+            stats: OrderStats::default()
+        };
+        set_exits(&mut bracket_config, token1_amount * util::decimal() / token2_amount)?;
+        Ok((bracket_config, Transition::new("").swap(SwapKind::Start, token1_amount)))
+    }
+
+    fn on_price(
+        &mut self,
+        quote: &dyn Quote,
+        _keeper_fee_bps: u64,
+        _now: u64
+    ) -> Result<Transition, ContractError> {
+        self.check_exits(quote)?;
+        match self.exit {
+            None => Ok(Transition::new("waiting")),
+            // a take profit sells at no less than its price, a stop loss fills at any price
+            Some(BracketExit::TakeProfit) => Ok(self.close().at_limit(self.take_profit_price)),
+            Some(BracketExit::StopLoss) => Ok(self.close())
+        }
+    }
+
+    // a stop or an expiry must always close, an exit already crossed is only recorded
+    fn on_stop(
+        &mut self,
+        quote: &dyn Quote
+    ) -> Result<Transition, ContractError> {
+        self.check_exits(quote)?;
+        Ok(self.close())
+    }
+}
+
+pub fn execute_stop_bracket(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64
+) -> Result<Response, ContractError> {
+    Ok(execute_sync_bracket(deps, env, address.clone(), Some(address.clone()), id, true)?)
+}
+
+pub fn execute_sync_bracket(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    address: Option<Addr>,
+    id: u64,
+    force_finish: bool
+) -> Result<Response, ContractError> {
+    sync_order::<BracketConfig>(deps, env, caller, address, id, force_finish)
+}
+
+pub fn reply_swap_bracket(
+    deps: DepsMut,
+    pending: PendingSwap,
+    amount: Uint128
+) -> Result<(Response, Vec<Transfer>), ContractError> {
+    let mut bracket_config = bracket_orders().load(deps.storage, (pending.address.clone(), pending.id))?;
+    let mut transfers: Vec<Transfer> = vec![];
+    let mut keeper_tip = Uint128::zero();
+
+    if pending.kind == SwapKind::Finish {
+        //pay the keeper and transfer the rest to sender
+        keeper_tip = util::get_keeper_tip(deps.storage, &pending.keeper, &pending.address, amount)?;
+        bracket_config.token1_amount = amount - keeper_tip;
+        bracket_config.token2_amount = Uint128::zero();
+
+        //performance fee on the realized profit
+        let (fee, fee_recipient) = util::take_performance_fee(deps.storage, &bracket_config.msg.token1_denom, bracket_config.initial_token1_amount, bracket_config.token1_amount)?;
+        bracket_config.token1_amount -= fee;
+        bracket_config.stats.performance_fees += fee;
+        util::add_realized_pnl(&mut bracket_config.stats, bracket_config.initial_token1_amount, bracket_config.token1_amount);
+
+        transfers.push((bracket_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+        transfers.push((bracket_config.msg.token1_denom.clone(), fee, fee_recipient));
+        transfers.push((bracket_config.msg.token1_denom.clone(), bracket_config.token1_amount, pending.address.clone()));
+    } else {
+        // the exits follow the price actually paid for the position
+        bracket_config.token2_amount = amount;
+        if !amount.is_zero() {
+            let entry_price = bracket_config.initial_token1_amount * util::decimal() / amount;
+            set_exits(&mut bracket_config, entry_price)?;
+        }
+    }
+    bracket_config.stats.keeper_fees += keeper_tip;
+    bracket_orders().save(deps.storage, (pending.address.clone(), pending.id), &bracket_config)?;
+    if bracket_config.finished {
+        archive_order(deps.storage, OrderType::Bracket, &pending.address, pending.id)?;
+    }
+
+    Ok((Response::new()
+        .add_attribute("action", "reply_swap_bracket")
+        .add_attribute("sender", pending.address.to_string())
+        .add_attribute("id", pending.id.to_string())
+        .add_attribute("keeper", pending.keeper.to_string())
+        .add_attribute("keeper_tip", keeper_tip), transfers))
+}
@@ -0,0 +1,189 @@
+use cosmwasm_std::{
+    DepsMut, Env, Response, Uint128, Addr
+};
+use cw20::{Balance, Denom};
+use crate::error::ContractError;
+use crate::msg::{
+    OrderType, DexKind, DcaMsg, DcaConfig, OrderStats
+};
+use crate::state::{
+    PendingSwap, SwapKind, dca_orders
+};
+
+use crate::ordergroup::{
+    archive_order, start_order, sync_order, Transfer, OrderStrategy, OrderTerms, Quote, Transition
+};
+use crate::util;
+
+pub fn execute_start_dca(
+    deps: DepsMut,
+    env: Env,
+    msg: DcaMsg,
+    balance: Balance,
+    address: Addr,
+) -> Result<Response, ContractError> {
+    start_order::<DcaConfig>(deps, env, msg, balance, address)
+}
+
+impl OrderStrategy for DcaConfig {
+    type Msg = DcaMsg;
+    const ORDER_TYPE: OrderType = OrderType::Dca;
+
+    fn terms(msg: &DcaMsg) -> OrderTerms {
+        OrderTerms {
+            token1_denom: msg.token1_denom.clone(),
+            pool_address: msg.pool_address.clone(),
+            max_slippage_bps: msg.max_slippage_bps,
+            expires_at: msg.expires_at
+        }
+    }
+
+    fn msg(&self) -> &DcaMsg {
+        &self.msg
+    }
+
+    fn token2_denom(&self) -> Denom {
+        self.token2_denom.clone()
+    }
+
+    fn dex(&self) -> DexKind {
+        self.dex
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // The first round is bought at once, what is sent above every round is refunded
+    fn on_start(
+        msg: DcaMsg,
+        token1_amount: Uint128,
+        token2_denom: Denom,
+        dex: DexKind,
+        _quote: &dyn Quote,
+        now: u64
+    ) -> Result<(Self, Transition), ContractError> {
+        if msg.num_rounds == 0u64 || msg.interval == 0u64 || msg.round_amount.is_zero() {
+            return Err(ContractError::InvalidInput {});
+        }
+
+        //check if token1_amount covers every round
+        let total_amount = msg.round_amount * Uint128::from(msg.num_rounds);
+        if token1_amount < total_amount {
+            return Err(ContractError::InsufficientAmountForDcaOrder {});
+        }
+
+        let round_amount = msg.round_amount;
+        let dca_config = DcaConfig {
+            msg,
+            token2_denom,
+            dex,
+            token1_amount: total_amount - round_amount,
+            keeper_tip: Uint128::zero(),
+            token2_amount: Uint128::zero(),
+            rounds_executed: 1u64,
+            last_execution: now,
+            finished: false,
+            stats: OrderStats::default()
+        };
+        Ok((dca_config, Transition::new("")
+            .refund(token1_amount - total_amount)
+            .swap(SwapKind::Start, round_amount)
+        ))
+    }
+
+    fn on_price(
+        &mut self,
+        _quote: &dyn Quote,
+        keeper_fee_bps: u64,
+        now: u64
+    ) -> Result<Transition, ContractError> {
+        //every round due since the last execution is bought with a single swap
+        let rounds_left = self.msg.num_rounds - self.rounds_executed;
+        let rounds_due = (now.saturating_sub(self.last_execution) / self.msg.interval).min(rounds_left);
+        if rounds_due == 0u64 {
+            return Ok(Transition::new("waiting").add_attribute("rounds", rounds_due.to_string()));
+        }
+
+        // the keeper tip is kept back from the rounds bought, the reply pays exactly this
+        let round_amount = self.msg.round_amount * Uint128::from(rounds_due);
+        let keeper_tip = round_amount * Uint128::from(keeper_fee_bps) / util::bps_multiple();
+        let buy_amount = round_amount - keeper_tip;
+
+        self.token1_amount -= buy_amount;
+        self.keeper_tip = keeper_tip;
+        self.rounds_executed += rounds_due;
+        self.last_execution += rounds_due * self.msg.interval;
+        Ok(Transition::new("round")
+            .swap(SwapKind::Buy, buy_amount)
+            .add_attribute("rounds", rounds_due.to_string())
+        )
+    }
+
+    // refund the unspent token1 together with the token2 bought so far
+    fn on_stop(
+        &mut self,
+        _quote: &dyn Quote
+    ) -> Result<Transition, ContractError> {
+        self.finished = true;
+        Ok(Transition::new("success")
+            .refund(self.token1_amount)
+            .refund_token2(self.token2_amount)
+        )
+    }
+}
+
+pub fn execute_stop_dca(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64
+) -> Result<Response, ContractError> {
+    Ok(execute_sync_dca(deps, env, address.clone(), Some(address.clone()), id, true)?)
+}
+
+pub fn execute_sync_dca(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    address: Option<Addr>,
+    id: u64,
+    force_finish: bool
+) -> Result<Response, ContractError> {
+    sync_order::<DcaConfig>(deps, env, caller, address, id, force_finish)
+}
+
+pub fn reply_swap_dca(
+    deps: DepsMut,
+    pending: PendingSwap,
+    amount: Uint128
+) -> Result<(Response, Vec<Transfer>), ContractError> {
+    let mut dca_config = dca_orders().load(deps.storage, (pending.address.clone(), pending.id))?;
+    let mut transfers: Vec<Transfer> = vec![];
+
+    //pay the keeper exactly the token1 kept back at the sync
+    let keeper_tip = dca_config.keeper_tip;
+    dca_config.token2_amount += amount;
+    dca_config.token1_amount -= keeper_tip;
+    dca_config.keeper_tip = Uint128::zero();
+    dca_config.stats.keeper_fees += keeper_tip;
+    transfers.push((dca_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+
+    // After the last round everything bought goes to the sender
+    if dca_config.rounds_executed >= dca_config.msg.num_rounds {
+        transfers.push((dca_config.token2_denom.clone(), dca_config.token2_amount, pending.address.clone()));
+        transfers.push((dca_config.msg.token1_denom.clone(), dca_config.token1_amount, pending.address.clone()));
+        dca_config.finished = true;
+    }
+    dca_orders().save(deps.storage, (pending.address.clone(), pending.id), &dca_config)?;
+    if dca_config.finished {
+        archive_order(deps.storage, OrderType::Dca, &pending.address, pending.id)?;
+    }
+
+    Ok((Response::new()
+        .add_attribute("action", "reply_swap_dca")
+        .add_attribute("sender", pending.address.to_string())
+        .add_attribute("id", pending.id.to_string())
+        .add_attribute("keeper", pending.keeper.to_string())
+        .add_attribute("keeper_tip", keeper_tip), transfers))
+}
@@ -1,190 +1,551 @@
 use cosmwasm_std::{
-    DepsMut, Response, Uint128, Addr, CosmosMsg
+    Deps, DepsMut, Env, Response, Uint128, Addr, StdError, Event
 };
-use cw20::Balance;
+use cw20::{Balance, Denom};
 use crate::error::ContractError;
 use crate::msg::{
-    GridMsg, GridConfig
+    OrderType, DexKind, GridMsg, GridConfig, GridLevel, GridLevelState, GridSpacing, GridAmendment, SimulateGridResponse, OrderStats
 };
 use crate::state::{
-    GRID_ORDERS_COUNT, GRID_ORDERS
+    GRID_ORDERS_COUNT, grid_orders, PendingSwap, SwapKind
 };
 
+use crate::ordergroup::{
+    archive_order, start_order, sync_order, simulate_order, Transfer, OrderStrategy, OrderTerms, Quote, Transition
+};
 use crate::util;
 
 pub fn execute_start_grid(
     deps: DepsMut,
+    env: Env,
     msg: GridMsg,
     balance: Balance,
     address: Addr,
 ) -> Result<Response, ContractError> {
-    if balance.is_empty() {
-        return Err(ContractError::EmptyBalance {});
+    start_order::<GridConfig>(deps, env, msg, balance, address)
+}
+
+pub fn query_simulate_grid(
+    deps: Deps,
+    env: Env,
+    msg: GridMsg,
+    amount: Uint128
+) -> Result<SimulateGridResponse, ContractError> {
+    let (grid_config, transition) = simulate_order::<GridConfig>(deps, env, msg, amount)?;
+    let swap_amount = transition.swap.map(|(_kind, swap_amount)| swap_amount).unwrap_or_default();
+
+    Ok(SimulateGridResponse {
+        deposit_amount: grid_config.msg.total_amount,
+        refund_amount: transition.refund,
+        swap_amount,
+        swap_output: grid_config.token2_amount,
+        grid_order: grid_config
+    })
+}
+
+impl OrderStrategy for GridConfig {
+    type Msg = GridMsg;
+    const ORDER_TYPE: OrderType = OrderType::Grid;
+
+    fn terms(msg: &GridMsg) -> OrderTerms {
+        OrderTerms {
+            token1_denom: msg.token1_denom.clone(),
+            pool_address: msg.pool_address.clone(),
+            max_slippage_bps: msg.max_slippage_bps,
+            expires_at: msg.expires_at
+        }
     }
-    // Update GRID_ORDERS_COUNT
-    let (mut list, max_number) = GRID_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
 
-    if list.len() as u64 >= util::MAX_ORDER {
-        return Err(ContractError::MaxOrderCountExceed {});
+    fn msg(&self) -> &GridMsg {
+        &self.msg
     }
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    
-    // UPDATE GRID_ORDERS
-    let _first_token = util::check_token_and_pool(deps.querier, msg.token1_denom.clone(), msg.pool_address.clone())?;
-    let mut token1_amount = util::get_amount_of_denom(balance, msg.token1_denom.clone())?;
+    fn token2_denom(&self) -> Denom {
+        self.token2_denom.clone()
+    }
 
-    if token1_amount < msg.total_amount {
-        return Err(ContractError::InsufficientAmountForGridOrder {});
-    } else if token1_amount > msg.total_amount {
-        messages.push(util::transfer_token_message(deps.querier, msg.token1_denom.clone(), token1_amount - msg.total_amount, address.clone())?);
-        token1_amount = msg.total_amount;
+    fn dex(&self) -> DexKind {
+        self.dex
     }
-    list.push(max_number);
-    GRID_ORDERS_COUNT.save(deps.storage, address.clone(), &(list, max_number + 1))?;
 
-    // Do the initial swap
-    let first_swap_amount = token1_amount / Uint128::from(2u128);
-    let (token2_amount, token2_denom, mut messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, msg.pool_address.clone(), msg.token1_denom.clone(), first_swap_amount)?;
-    messages.append(&mut messages_swap);
-    let avg_buy_price = first_swap_amount * util::decimal() / token2_amount;
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
 
-    // Update GRID_ORDERS
-    //make dca_prices, dca_amounts list
-    let mut sell_prices:Vec<Uint128> = vec![];
-    let mut buy_prices:Vec<Uint128> = vec![];
-    
-    let delta = msg.price_range_percentage / msg.num_grid_pairs;
-    for i in 0..msg.num_grid_pairs {
-        sell_prices.push(avg_buy_price * (util::multiple() + Uint128::from(delta * (i + 1))) / util::multiple() );
-        buy_prices.push(avg_buy_price * (util::multiple() - Uint128::from(delta * (i + 1))) / util::multiple() );
+    // The initial swap buys half the total amount, what is sent above the total amount is refunded
+    fn on_start(
+        msg: GridMsg,
+        token1_amount: Uint128,
+        token2_denom: Denom,
+        dex: DexKind,
+        quote: &dyn Quote,
+        _now: u64
+    ) -> Result<(Self, Transition), ContractError> {
+        if msg.num_grid_pairs == 0u64 {
+            return Err(ContractError::InvalidGridPairs {});
+        }
+        if token1_amount < msg.total_amount {
+            return Err(ContractError::InsufficientAmountForGridOrder {});
+        }
+
+        // Do the initial swap
+        let first_swap_amount = msg.total_amount / Uint128::from(2u128);
+        // every buy level needs something to buy with
+        let order_amount = (msg.total_amount - first_swap_amount) / Uint128::from(msg.num_grid_pairs);
+        if order_amount.is_zero() {
+            return Err(ContractError::InsufficientAmountForGridOrder {});
+        }
+        let token2_amount = quote.buy(first_swap_amount)?;
+        let avg_buy_price = first_swap_amount * util::decimal() / token2_amount;
+
+        // one ladder from the lowest buy level to the highest sell level, the start price in the middle
+        let (buy_prices, sell_prices) = get_grid_prices(&msg, avg_buy_price)?;
+        let mut levels:Vec<GridLevel> = vec![];
         
+        for price in buy_prices.into_iter().rev() {
+            levels.push(GridLevel {
+                price,
+                state: GridLevelState::Buy,
+                rearmed: false
+            });
+        }
+        levels.push(GridLevel {
+            price: avg_buy_price,
+            state: GridLevelState::Idle,
+            rearmed: false
+        });
+        for price in sell_prices {
+            levels.push(GridLevel {
+                price,
+                state: GridLevelState::Sell,
+                rearmed: false
+            });
+        }
+
+        let refund_amount = token1_amount - msg.total_amount;
+        let grid_config = GridConfig {
+            order_amount,
+            token1_amount: msg.total_amount - first_swap_amount,
+            msg,
+            token2_denom,
+            dex,
+            levels,
+            finished: false,
+            stats: OrderStats::default(),
+            round_trips: 0u64,
+            token2_amount
+        };
+        Ok((grid_config, Transition::new("")
+            .refund(refund_amount)
+            .swap(SwapKind::Start, first_swap_amount)
+        ))
     }
 
-    let grid_config = GridConfig {
-        msg: msg.clone(),
-        token2_denom,
-        buy_prices,
-        sell_prices,
-        order_amount: (msg.total_amount - first_swap_amount) / Uint128::from(msg.num_grid_pairs),
-        finished: false,
-        buy_step: 0u64,
-        sell_step: 0u64,
-        token1_amount: token1_amount - first_swap_amount,
-        token2_amount
-    };
+    fn on_price(
+        &mut self,
+        quote: &dyn Quote,
+        _keeper_fee_bps: u64,
+        _now: u64
+    ) -> Result<Transition, ContractError> {
+        let swap_amount = quote.buy(self.order_amount)?;
+        let buy_price = self.order_amount * util::decimal() / swap_amount;
 
-    GRID_ORDERS.save(deps.storage, (address.clone(), max_number), &grid_config)?;
-    
-    Ok(Response::new()
-        .add_attribute("action", "start_grid")
-        .add_attribute("address", address.clone().to_string())
-        .add_messages(messages)
-    )
+        //every buy level reached is filled with a single swap, each one arms a sell one level above
+        let mut buy_amount = Uint128::zero();
+        let mut filled: Vec<usize> = vec![];
+        for i in (0..self.levels.len()).rev() {
+            if self.token1_amount < buy_amount + self.order_amount {
+                break;
+            }
+            if self.levels[i].state == GridLevelState::Buy && buy_price <= self.levels[i].price {
+                buy_amount += self.order_amount;
+                filled.push(i);
+            }
+        }
+
+        //each sell level reached re-arms a buy one level below, only when nothing was bought
+        let mut sell_amount = Uint128::zero();
+        if filled.is_empty() {
+            let sell_price = quote.sell(swap_amount)? * util::decimal() / swap_amount;
+
+            for i in 0..self.levels.len() {
+                if self.token2_amount < sell_amount + swap_amount {
+                    break;
+                }
+                if self.levels[i].state == GridLevelState::Sell && sell_price >= self.levels[i].price {
+                    sell_amount += swap_amount;
+                    filled.push(i);
+                }
+            }
+        }
+
+        for i in filled.iter() {
+            if self.levels[*i].rearmed {
+                self.round_trips += 1u64;
+            }
+            self.levels[*i].state = GridLevelState::Idle;
+            self.levels[*i].rearmed = false;
+        }
+        for i in filled.iter() {
+            let (next, state) = if !buy_amount.is_zero() {
+                (Some(*i + 1), GridLevelState::Sell)
+            } else {
+                (i.checked_sub(1), GridLevelState::Buy)
+            };
+            if let Some(level) = next.and_then(|next| self.levels.get_mut(next)) {
+                if level.state == GridLevelState::Idle {
+                    level.state = state;
+                    level.rearmed = true;
+                }
+            }
+        }
+
+        // buy or sell atom, the swapped amount is added in the reply
+        // the swap fills at no worse than the least favourable level it covers, the first one filled
+        let mut transition = Transition::new("waiting");
+        if let Some(first) = filled.first() {
+            let level_price = self.levels[*first].price;
+            if !buy_amount.is_zero() {
+                self.token1_amount -= buy_amount;
+                transition = Transition::new("buy").swap(SwapKind::Buy, buy_amount).at_limit(level_price);
+            } else {
+                self.token2_amount -= sell_amount;
+                transition = Transition::new("sell").swap(SwapKind::Sell, sell_amount).at_limit(level_price);
+            }
+        }
+        Ok(transition.add_attribute("round_trips", self.round_trips.to_string()))
+    }
+
+    // Do final swap, the output and the remaining token1 are paid out to sender in the reply
+    fn on_stop(
+        &mut self,
+        _quote: &dyn Quote
+    ) -> Result<Transition, ContractError> {
+        self.finished = true;
+        Ok(Transition::new("success")
+            .swap(SwapKind::Finish, self.token2_amount)
+            .add_attribute("round_trips", self.round_trips.to_string())
+        )
+    }
 }
 
+// Buy prices going down and sell prices going up from the start price, one per grid pair
+fn get_grid_prices(
+    msg: &GridMsg,
+    start_price: Uint128
+) -> Result<(Vec<Uint128>, Vec<Uint128>), ContractError> {
+    let (lower_price, upper_price) = match (msg.lower_price, msg.upper_price, msg.price_range_percentage) {
+        (Some(lower_price), Some(upper_price), _) => (lower_price, upper_price),
+        (None, None, Some(percentage)) => {
+            if percentage == 0u64 || Uint128::from(percentage) >= util::multiple() {
+                return Err(ContractError::InvalidGridRange {});
+            }
+            (
+                start_price * (util::multiple() - Uint128::from(percentage)) / util::multiple(),
+                start_price * (util::multiple() + Uint128::from(percentage)) / util::multiple()
+            )
+        },
+        _ => {
+            return Err(ContractError::InvalidGridRange {});
+        }
+    };
+    if lower_price.is_zero() || lower_price >= start_price || upper_price <= start_price {
+        return Err(ContractError::GridPriceOutOfBounds {});
+    }
 
+    let num_grid_pairs = Uint128::from(msg.num_grid_pairs);
+    let mut buy_prices:Vec<Uint128> = vec![];
+    let mut sell_prices:Vec<Uint128> = vec![];
+    match msg.spacing.clone().unwrap_or(GridSpacing::Arithmetic) {
+        GridSpacing::Arithmetic => {
+            for i in 1..=msg.num_grid_pairs {
+                buy_prices.push(start_price - (start_price - lower_price) * Uint128::from(i) / num_grid_pairs);
+                sell_prices.push(start_price + (upper_price - start_price) * Uint128::from(i) / num_grid_pairs);
+            }
+        },
+        GridSpacing::Geometric => {
+            // every level is the same ratio away from the previous one
+            let buy_ratio = get_step_ratio(lower_price, start_price, msg.num_grid_pairs)?;
+            let sell_ratio = get_step_ratio(start_price, upper_price, msg.num_grid_pairs)?;
+            let mut buy_price = start_price;
+            let mut sell_price = start_price;
+            for _i in 0..msg.num_grid_pairs {
+                buy_price = buy_price * util::decimal() / buy_ratio;
+                sell_price = sell_price * sell_ratio / util::decimal();
+                buy_prices.push(buy_price);
+                sell_prices.push(sell_price);
+            }
+        }
+    }
 
-pub fn execute_stop_grid(
+    // Levels closer than the price precision can't be told apart
+    let mut previous = start_price;
+    for price in buy_prices.iter() {
+        if price.is_zero() || *price >= previous {
+            return Err(ContractError::GridSpacingTooSmall {});
+        }
+        previous = *price;
+    }
+    let mut previous = start_price;
+    for price in sell_prices.iter() {
+        if *price <= previous {
+            return Err(ContractError::GridSpacingTooSmall {});
+        }
+        previous = *price;
+    }
+    Ok((buy_prices, sell_prices))
+}
+
+// Ratio between two levels, multiplied by decimal(), so that `steps` steps go from `from_price` to `to_price`
+fn get_step_ratio(
+    from_price: Uint128,
+    to_price: Uint128,
+    steps: u64
+) -> Result<Uint128, ContractError> {
+    let mut low = util::decimal();
+    let mut high = util::decimal() * to_price / from_price + Uint128::from(1u128);
+    while low + Uint128::from(1u128) < high {
+        let middle = (low + high) / Uint128::from(2u128);
+        let mut price = from_price;
+        for _i in 0..steps {
+            price = price.checked_mul(middle).map_err(StdError::from)? / util::decimal();
+            if price > to_price {
+                break;
+            }
+        }
+        if price > to_price {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    Ok(low)
+}
+
+pub fn execute_increase_grid(
     deps: DepsMut,
     address: Addr,
-    id: u64
+    id: u64,
+    balance: Balance
 ) -> Result<Response, ContractError> {
-    Ok(execute_sync_grid(deps, address.clone(), Some(address.clone()), id, true)?)
+    if balance.is_empty() {
+        return Err(ContractError::EmptyBalance {});
+    }
+    let (list, _max_number) = GRID_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
+    if !list.contains(&id) {
+        return Err(ContractError::OrderNotExist {});
+    }
+    let mut grid_config = grid_orders().load(deps.storage, (address.clone(), id))?;
+    if grid_config.finished {
+        return Err(ContractError::AlreadyFinishedOrder {});
+    }
+    // no new funds into a pool that was removed or disabled
+    util::check_pool_registered(deps.storage, grid_config.msg.pool_address.clone())?;
+    let token1_amount = util::get_amount_of_denom(balance, grid_config.msg.token1_denom.clone())?;
+
+    // every level buys a larger share from now on
+    grid_config.msg.total_amount += token1_amount;
+    grid_config.token1_amount += token1_amount;
+    grid_config.order_amount += token1_amount / Uint128::from(grid_config.msg.num_grid_pairs);
+    grid_orders().save(deps.storage, (address.clone(), id), &grid_config)?;
+    util::increase_escrow(deps.storage, &grid_config.msg.token1_denom, token1_amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "increase_grid")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_attribute("amount", token1_amount)
+        .add_attribute("order_amount", grid_config.order_amount)
+    )
 }
 
-pub fn execute_sync_grid(
+pub fn execute_decrease_grid(
     deps: DepsMut,
-    caller: Addr, 
-    address: Option<Addr>,
+    address: Addr,
     id: u64,
-    force_finish: bool
+    amount: Uint128
 ) -> Result<Response, ContractError> {
-
-    let real_address;
-    match address {
-        Some(addr) => {real_address = addr.clone();},
-        None => {real_address = caller.clone();}
-    }
-
-    if real_address != caller.clone() {
-        util::check_owner(deps.storage, caller.clone())?;
-    }
-    
-    let (mut list, _max_number) = GRID_ORDERS_COUNT.load(deps.storage, real_address.clone()).unwrap_or((vec![], 0));
+    let (list, _max_number) = GRID_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
     if !list.contains(&id) {
         return Err(ContractError::OrderNotExist {});
     }
-    let mut grid_config = GRID_ORDERS.load(deps.storage, (real_address.clone(), id))?;
-
+    let mut grid_config = grid_orders().load(deps.storage, (address.clone(), id))?;
     if grid_config.finished {
         return Err(ContractError::AlreadyFinishedOrder {});
     }
+    if amount.is_zero() || amount > grid_config.token1_amount {
+        return Err(ContractError::InsufficientOrderAmount {});
+    }
 
-    //check the current_dca_point and do swap Juno->Atom while the current buy price is larger than dca_price
+    // every level buys a smaller share, the token1 left must still cover the armed buy levels
+    let order_amount = grid_config.order_amount.checked_sub(amount / Uint128::from(grid_config.msg.num_grid_pairs)).map_err(StdError::from)?;
+    let armed_buys = grid_config.levels.iter().filter(|level| level.state == GridLevelState::Buy).count();
+    if order_amount.is_zero() || grid_config.token1_amount - amount < order_amount * Uint128::from(armed_buys as u64) {
+        return Err(ContractError::LadderUnderfunded {});
+    }
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    //sell atom
+    grid_config.msg.total_amount -= amount;
+    grid_config.token1_amount -= amount;
+    grid_config.order_amount = order_amount;
+    grid_orders().save(deps.storage, (address.clone(), id), &grid_config)?;
+    util::decrease_escrow(deps.storage, &grid_config.msg.token1_denom, amount)?;
 
-    while grid_config.buy_step < grid_config.msg.num_grid_pairs {
-        let (swap_amount, _other_denom, mut messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, grid_config.msg.pool_address.clone(), grid_config.msg.token1_denom.clone(), grid_config.order_amount)?;
-        let price = grid_config.order_amount * util::decimal() / swap_amount;
+    Ok(Response::new()
+        .add_attribute("action", "decrease_grid")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("order_amount", grid_config.order_amount)
+        .add_message(util::transfer_token_message(deps.querier, grid_config.msg.token1_denom.clone(), amount, address.clone())?)
+    )
+}
 
-        if price <= grid_config.buy_prices[grid_config.buy_step as usize] {
-            // do the swap
-            messages.append(&mut messages_swap);
-            grid_config.token1_amount -= grid_config.order_amount;
-            grid_config.token2_amount += swap_amount;
+pub fn execute_amend_grid(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64,
+    amendment: GridAmendment
+) -> Result<Response, ContractError> {
+    let (list, _max_number) = GRID_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
+    if !list.contains(&id) {
+        return Err(ContractError::OrderNotExist {});
+    }
+    let mut grid_config = grid_orders().load(deps.storage, (address.clone(), id))?;
+    if grid_config.finished {
+        return Err(ContractError::AlreadyFinishedOrder {});
+    }
 
-            grid_config.buy_step += 1u64;
-        } else {
-            break;
+    let mut event = Event::new("amend_order")
+        .add_attribute("order_type", "grid")
+        .add_attribute("id", id.to_string());
+    // the range is either a percentage or both bounds, one replaces the other
+    match (amendment.price_range_percentage, amendment.lower_price, amendment.upper_price) {
+        (None, None, None) => {},
+        (Some(percentage), None, None) => {
+            grid_config.msg.price_range_percentage = Some(percentage);
+            grid_config.msg.lower_price = None;
+            grid_config.msg.upper_price = None;
+            event = event.add_attribute("price_range_percentage", percentage.to_string());
+        },
+        (None, Some(lower_price), Some(upper_price)) => {
+            grid_config.msg.price_range_percentage = None;
+            grid_config.msg.lower_price = Some(lower_price);
+            grid_config.msg.upper_price = Some(upper_price);
+            event = event.add_attribute("lower_price", lower_price).add_attribute("upper_price", upper_price);
+        },
+        _ => {
+            return Err(ContractError::InvalidGridRange {});
         }
     }
+    if let Some(spacing) = amendment.spacing {
+        event = event.add_attribute("spacing", match spacing {
+            GridSpacing::Arithmetic => "arithmetic",
+            GridSpacing::Geometric => "geometric"
+        });
+        grid_config.msg.spacing = Some(spacing);
+    }
+    if let Some(max_slippage_bps) = amendment.max_slippage_bps {
+        grid_config.msg.max_slippage_bps = Some(util::check_max_slippage(max_slippage_bps)?);
+        event = event.add_attribute("max_slippage_bps", max_slippage_bps.to_string());
+    }
+    if let Some(expires_at) = amendment.expires_at {
+        util::check_expires_at(&env, Some(expires_at))?;
+        grid_config.msg.expires_at = Some(expires_at);
+        event = event.add_attribute("expires_at", expires_at.to_string());
+    }
+
+    // every level is priced again around the start price, the armed states stay
+    let center = grid_config.msg.num_grid_pairs as usize;
+    let (buy_prices, sell_prices) = get_grid_prices(&grid_config.msg, grid_config.levels[center].price)?;
+    for (i, price) in buy_prices.into_iter().enumerate() {
+        grid_config.levels[center - 1 - i].price = price;
+    }
+    for (i, price) in sell_prices.into_iter().enumerate() {
+        grid_config.levels[center + 1 + i].price = price;
+    }
+    grid_orders().save(deps.storage, (address.clone(), id), &grid_config)?;
 
-    while grid_config.sell_step < grid_config.msg.num_grid_pairs {
-        let (swap_amount, _other_denom, _temp_message) = util::get_swap_amount_and_denom_and_message(deps.querier, grid_config.msg.pool_address.clone(), grid_config.msg.token1_denom.clone(), grid_config.order_amount)?;
+    Ok(Response::new()
+        .add_attribute("action", "amend_grid")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_event(event)
+    )
+}
 
-        let (token1_swap_amount, _token1_denom, mut messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, grid_config.msg.pool_address.clone(), grid_config.token2_denom.clone(), swap_amount)?;
+pub fn execute_stop_grid(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64
+) -> Result<Response, ContractError> {
+    Ok(execute_sync_grid(deps, env, address.clone(), Some(address.clone()), id, true)?)
+}
 
-        let price = token1_swap_amount * util::decimal() / swap_amount;
+pub fn execute_sync_grid(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    address: Option<Addr>,
+    id: u64,
+    force_finish: bool
+) -> Result<Response, ContractError> {
+    sync_order::<GridConfig>(deps, env, caller, address, id, force_finish)
+}
 
-        if price >= grid_config.sell_prices[grid_config.sell_step as usize] {
-            // do the swap
-            messages.append(&mut messages_swap);
-            grid_config.token1_amount += token1_swap_amount;
-            grid_config.token2_amount -= swap_amount;
+pub fn reply_swap_grid(
+    deps: DepsMut,
+    pending: PendingSwap,
+    amount: Uint128
+) -> Result<(Response, Vec<Transfer>), ContractError> {
+    let mut grid_config = grid_orders().load(deps.storage, (pending.address.clone(), pending.id))?;
+    let mut transfers: Vec<Transfer> = vec![];
+    let mut keeper_tip = Uint128::zero();
 
-            grid_config.sell_step += 1u64;
-        } else {
-            break;
-        }
-    }
-    
-    let mut action = String::from("sync_grid_waiting");
-    //check if the ATOM->swap rate is larger than avg_buy_price
-    if force_finish {
-        let (index, _max_number) =list.iter().enumerate().find(|(_i, c)| c == &&id).unwrap_or((0, &0));
-        list.remove(index);
-        // Do final swap
-        let (token1_swap_amount, _token1_denom, mut messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, grid_config.msg.pool_address.clone(), grid_config.token2_denom.clone(), grid_config.token2_amount)?;
-        messages.append(&mut messages_swap);
-        
-        //transfer to sender
-        messages.push(util::transfer_token_message(deps.querier, grid_config.msg.token1_denom.clone(), token1_swap_amount + grid_config.token1_amount, real_address.clone())?);
+    match pending.kind {
+        SwapKind::Start => {
+            grid_config.token2_amount = amount;
+        },
+        SwapKind::Buy | SwapKind::Increase => {
+            //pay the keeper from the order's token1
+            keeper_tip = util::get_keeper_tip(deps.storage, &pending.keeper, &pending.address, pending.input_amount)?.min(grid_config.token1_amount);
+            grid_config.token1_amount -= keeper_tip;
+            grid_config.token2_amount += amount;
+            transfers.push((grid_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+        },
+        SwapKind::Sell => {
+            keeper_tip = util::get_keeper_tip(deps.storage, &pending.keeper, &pending.address, amount)?;
+            grid_config.token1_amount = grid_config.token1_amount + amount - keeper_tip;
+            transfers.push((grid_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+        },
+        SwapKind::Finish => {
+            //pay the keeper and transfer the rest to sender
+            keeper_tip = util::get_keeper_tip(deps.storage, &pending.keeper, &pending.address, amount)?;
+            grid_config.token1_amount = grid_config.token1_amount + amount - keeper_tip;
+            grid_config.token2_amount = Uint128::zero();
 
-        grid_config.finished = true;
-        grid_config.token1_amount += token1_swap_amount;
-        grid_config.token2_amount = Uint128::zero();
-        action = String::from("sync_grid_success");
+            //performance fee on the realized profit
+            let (fee, fee_recipient) = util::take_performance_fee(deps.storage, &grid_config.msg.token1_denom, grid_config.msg.total_amount, grid_config.token1_amount)?;
+            grid_config.token1_amount -= fee;
+            grid_config.stats.performance_fees += fee;
+            util::add_realized_pnl(&mut grid_config.stats, grid_config.msg.total_amount, grid_config.token1_amount);
 
+            transfers.push((grid_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+            transfers.push((grid_config.msg.token1_denom.clone(), fee, fee_recipient));
+            transfers.push((grid_config.msg.token1_denom.clone(), grid_config.token1_amount, pending.address.clone()));
+        }
+    }
+    grid_config.stats.keeper_fees += keeper_tip;
+    grid_orders().save(deps.storage, (pending.address.clone(), pending.id), &grid_config)?;
+    if grid_config.finished {
+        archive_order(deps.storage, OrderType::Grid, &pending.address, pending.id)?;
     }
-    GRID_ORDERS.save(deps.storage, (real_address.clone(), id), &grid_config)?;
 
-    return Ok(Response::new()
-        .add_attribute("action", action)
-        .add_attribute("sender", real_address.to_string())
-        .add_attribute("id", id.to_string())
-        .add_messages(messages)
-    );
+    Ok((Response::new()
+        .add_attribute("action", "reply_swap_grid")
+        .add_attribute("sender", pending.address.to_string())
+        .add_attribute("id", pending.id.to_string())
+        .add_attribute("keeper", pending.keeper.to_string())
+        .add_attribute("keeper_tip", keeper_tip), transfers))
 }
@@ -1,141 +1,459 @@
 use cosmwasm_std::{
-    DepsMut, Response, Uint128, Addr, CosmosMsg
+    Deps, DepsMut, Env, Response, Uint128, Addr, Event
 };
-use cw20::Balance;
+use cw20::{Balance, Denom};
 use crate::error::ContractError;
 use crate::msg::{
-    LimitMsg, LimitConfig
+    OrderType, DexKind, LimitMsg, LimitConfig, LimitPhase, LimitAmendment, SimulateLimitResponse, OrderStats
 };
 use crate::state::{
-    LIMIT_ORDERS_COUNT, LIMIT_ORDERS
+    LIMIT_ORDERS_COUNT, limit_orders, PendingSwap, SwapKind
 };
 
+use crate::ordergroup::{
+    queue_swap, archive_order, start_order, sync_order, simulate_order, Transfer, OrderStrategy, OrderTerms, Quote, Transition
+};
 use crate::util;
 
 pub fn execute_start_limit(
     deps: DepsMut,
+    env: Env,
     msg: LimitMsg,
     balance: Balance,
     address: Addr,
 ) -> Result<Response, ContractError> {
-    if balance.is_empty() {
-        return Err(ContractError::EmptyBalance {});
+    start_order::<LimitConfig>(deps, env, msg, balance, address)
+}
+
+pub fn query_simulate_limit(
+    deps: Deps,
+    env: Env,
+    msg: LimitMsg,
+    amount: Uint128
+) -> Result<SimulateLimitResponse, ContractError> {
+    let (limit_config, transition) = simulate_order::<LimitConfig>(deps, env, msg, amount)?;
+    // a limit buy swaps nothing until the entry price is reached
+    let swap_amount = transition.swap.map(|(_kind, swap_amount)| swap_amount).unwrap_or_default();
+    Ok(SimulateLimitResponse {
+        deposit_amount: amount - transition.refund,
+        refund_amount: transition.refund,
+        swap_amount,
+        swap_output: limit_config.token2_amount,
+        limit_order: limit_config
+    })
+}
+
+impl OrderStrategy for LimitConfig {
+    type Msg = LimitMsg;
+    const ORDER_TYPE: OrderType = OrderType::Limit;
+
+    fn terms(msg: &LimitMsg) -> OrderTerms {
+        OrderTerms {
+            token1_denom: msg.token1_denom.clone(),
+            pool_address: msg.pool_address.clone(),
+            max_slippage_bps: msg.max_slippage_bps,
+            expires_at: msg.expires_at
+        }
     }
-    // Update LIMIT_ORDERS_COUNT
-    let (mut list, max_number) = LIMIT_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
 
-    if list.len() as u64 >= util::MAX_ORDER {
-        return Err(ContractError::MaxOrderCountExceed {});
+    fn msg(&self) -> &LimitMsg {
+        &self.msg
     }
 
-    let _first_token = util::check_token_and_pool(deps.querier, msg.token1_denom.clone(), msg.pool_address.clone())?;
-    
-    let token1_amount = util::get_amount_of_denom(balance, msg.token1_denom.clone())?;
+    fn token2_denom(&self) -> Denom {
+        self.token2_denom.clone()
+    }
 
-    // Save current avg_buy_price
-    let (token2_amount, token2_denom, messages) = util::get_swap_amount_and_denom_and_message(deps.querier, msg.pool_address.clone(), msg.token1_denom.clone(), token1_amount)?;
+    fn dex(&self) -> DexKind {
+        self.dex
+    }
 
-    let avg_buy_price = token1_amount * util::decimal() / token2_amount;
-    let target_buy_price = avg_buy_price * (util::multiple() + Uint128::from(msg.take_profit_percentage)) / util::multiple();
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
 
-    list.push(max_number);
-    LIMIT_ORDERS_COUNT.save(deps.storage, address.clone(), &(list, max_number + 1))?;
+    fn on_start(
+        msg: LimitMsg,
+        token1_amount: Uint128,
+        token2_denom: Denom,
+        dex: DexKind,
+        quote: &dyn Quote,
+        _now: u64
+    ) -> Result<(Self, Transition), ContractError> {
+        let mut limit_config = LimitConfig {
+            msg,
+            phase: LimitPhase::TakeProfit,
+            avg_buy_price: Uint128::zero(),
+            target_buy_price: Uint128::zero(),
+            stop_loss_price: None,
+            peak_price: None,
+            trail_trigger_price: None,
+            initial_token1_amount: token1_amount,
+            token1_amount: Uint128::zero(),
+            token2_amount: Uint128::zero(),
+            token2_denom,
+            dex,
+            finished: false,
+            stats: OrderStats::default()
+        };
 
+        // A limit buy keeps the funds until the entry price is reached, the targets are set again on the entry
+        if let Some(entry_price) = limit_config.msg.entry_price {
+            if entry_price.is_zero() {
+                return Err(ContractError::InvalidInput {});
+            }
+            set_targets(&mut limit_config, entry_price)?;
+            limit_config.phase = LimitPhase::Entry;
+            limit_config.token1_amount = token1_amount;
+            return Ok((limit_config, Transition::new("entry")));
+        }
 
-    // Update LIMIT_ORDERS
-    let limit_config = LimitConfig {
-        msg: msg.clone(),
-        avg_buy_price,
-        target_buy_price,
-        initial_token1_amount: token1_amount,
-        token1_amount: Uint128::zero(),
-        token2_amount,
-        token2_denom,
-        finished: false
-    };
+        // Save current avg_buy_price
+        let token2_amount = quote.buy(token1_amount)?;
+        limit_config.token2_amount = token2_amount;
+        set_targets(&mut limit_config, token1_amount * util::decimal() / token2_amount)?;
+        Ok((limit_config, Transition::new("").swap(SwapKind::Start, token1_amount)))
+    }
 
-    LIMIT_ORDERS.save(deps.storage, (address.clone(), max_number), &limit_config)?;
-    
-    Ok(Response::new()
-        .add_attribute("action", "start_limit")
-        .add_attribute("address", address.clone().to_string())
-        .add_messages(messages)
-    )
+    fn on_price(
+        &mut self,
+        quote: &dyn Quote,
+        keeper_fee_bps: u64,
+        _now: u64
+    ) -> Result<Transition, ContractError> {
+        let current_buy_price = self.initial_token1_amount * util::decimal() / quote.buy(self.initial_token1_amount)?;
+
+        if self.phase == LimitPhase::Entry {
+            let entry_price = self.msg.entry_price.unwrap_or_default();
+            if current_buy_price > entry_price {
+                return Ok(Transition::new("waiting"));
+            }
+
+            // the keeper tip is kept back in token1 and paid in the reply
+            let keeper_tip = self.token1_amount * Uint128::from(keeper_fee_bps) / util::bps_multiple();
+            let buy_amount = self.token1_amount - keeper_tip;
+            set_targets(self, current_buy_price)?;
+            self.phase = LimitPhase::TakeProfit;
+            self.token1_amount = keeper_tip;
+            return Ok(Transition::new("entry").swap(SwapKind::Buy, buy_amount).at_limit(entry_price));
+        }
+
+        let stop_loss = match self.stop_loss_price {
+            Some(stop_loss_price) => current_buy_price < stop_loss_price,
+            None => false
+        };
+
+        let mut take_profit = current_buy_price > self.target_buy_price;
+
+        // In trailing mode follow the peak and only sell once the price falls back from it after passing the target
+        if let Some(trail_percentage) = self.msg.trail_percentage {
+            let peak_price = self.peak_price.unwrap_or(self.avg_buy_price).max(current_buy_price);
+            let trail_trigger_price = get_trail_trigger_price(peak_price, trail_percentage);
+
+            self.peak_price = Some(peak_price);
+            self.trail_trigger_price = Some(trail_trigger_price);
+            take_profit = peak_price > self.target_buy_price && current_buy_price <= trail_trigger_price;
+        }
+
+        if !take_profit && !stop_loss {
+            return Ok(Transition::new("waiting"));
+        }
+
+        //the output is paid out to sender in the reply
+        //a take profit sells at no less than its target, a stop loss or a trailing exit fills at any price
+        self.finished = true;
+        if stop_loss {
+            return Ok(Transition::new("stop_loss").swap(SwapKind::Finish, self.token2_amount));
+        }
+        let transition = Transition::new("success").swap(SwapKind::Finish, self.token2_amount);
+        if self.msg.trail_percentage.is_some() {
+            return Ok(transition);
+        }
+        Ok(transition.at_limit(self.target_buy_price))
+    }
+
+    fn on_stop(
+        &mut self,
+        _quote: &dyn Quote
+    ) -> Result<Transition, ContractError> {
+        self.finished = true;
+
+        // nothing was bought yet, give the deposit back
+        if self.phase == LimitPhase::Entry {
+            let refund_amount = self.token1_amount;
+            self.token1_amount = Uint128::zero();
+            return Ok(Transition::new("success").refund(refund_amount));
+        }
+        Ok(Transition::new("success").swap(SwapKind::Finish, self.token2_amount))
+    }
 }
 
+// Take profit, stop loss and trailing levels for the price the position was bought at
+fn set_targets(
+    limit_config: &mut LimitConfig,
+    avg_buy_price: Uint128
+) -> Result<(), ContractError> {
+    limit_config.avg_buy_price = avg_buy_price;
+    limit_config.target_buy_price = avg_buy_price * (util::multiple() + Uint128::from(limit_config.msg.take_profit_percentage)) / util::multiple();
+    limit_config.stop_loss_price = util::get_stop_loss_price(avg_buy_price, limit_config.msg.stop_loss_percentage)?;
 
+    if let Some(trail_percentage) = limit_config.msg.trail_percentage {
+        if trail_percentage == 0u64 || Uint128::from(trail_percentage) >= util::multiple() {
+            return Err(ContractError::InvalidInput {});
+        }
+        limit_config.peak_price = Some(avg_buy_price);
+        limit_config.trail_trigger_price = Some(get_trail_trigger_price(avg_buy_price, trail_percentage));
+    }
+    Ok(())
+}
 
-pub fn execute_stop_limit(
+fn get_trail_trigger_price(
+    peak_price: Uint128,
+    trail_percentage: u64
+) -> Uint128 {
+    peak_price * (util::multiple() - Uint128::from(trail_percentage)) / util::multiple()
+}
+
+pub fn execute_increase_limit(
     deps: DepsMut,
+    env: Env,
     address: Addr,
-    id: u64
+    id: u64,
+    balance: Balance
 ) -> Result<Response, ContractError> {
-    Ok(execute_sync_limit(deps, address.clone(), Some(address.clone()), id, true)?)
+    if balance.is_empty() {
+        return Err(ContractError::EmptyBalance {});
+    }
+    let (list, _max_number) = LIMIT_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
+    if !list.contains(&id) {
+        return Err(ContractError::OrderNotExist {});
+    }
+    let mut limit_config = limit_orders().load(deps.storage, (address.clone(), id))?;
+    if limit_config.finished {
+        return Err(ContractError::AlreadyFinishedOrder {});
+    }
+    // no new funds into a pool that was removed or disabled
+    util::check_pool_registered(deps.storage, limit_config.msg.pool_address.clone())?;
+    let max_slippage_bps = util::get_max_slippage(deps.storage, limit_config.msg.max_slippage_bps)?;
+    let token1_amount = util::get_amount_of_denom(balance, limit_config.msg.token1_denom.clone())?;
+
+    limit_config.initial_token1_amount += token1_amount;
+    util::increase_escrow(deps.storage, &limit_config.msg.token1_denom, token1_amount)?;
+
+    // A limit buy just waits with more funds
+    if limit_config.phase == LimitPhase::Entry {
+        limit_config.token1_amount += token1_amount;
+        limit_orders().save(deps.storage, (address.clone(), id), &limit_config)?;
+
+        return Ok(Response::new()
+            .add_attribute("action", "increase_limit")
+            .add_attribute("sender", address.to_string())
+            .add_attribute("id", id.to_string())
+            .add_attribute("amount", token1_amount)
+        );
+    }
+
+    // the position grows at the current price, avg_buy_price and the targets follow in the reply
+    let (_swap_amount, _other_denom, messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, limit_config.dex, limit_config.msg.pool_address.clone(), limit_config.msg.token1_denom.clone(), token1_amount, None, max_slippage_bps)?;
+    let sub_messages = queue_swap(deps.storage, deps.querier, env.contract.address.clone(), PendingSwap {
+        order_type: OrderType::Limit,
+        address: address.clone(),
+        id,
+        keeper: address.clone(),
+        kind: SwapKind::Increase,
+        input_amount: token1_amount,
+        input_denom: limit_config.msg.token1_denom.clone(),
+        output_denom: limit_config.token2_denom.clone(),
+        balance_before: Uint128::zero()
+    }, messages_swap)?;
+    limit_orders().save(deps.storage, (address.clone(), id), &limit_config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "increase_limit")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_attribute("amount", token1_amount)
+        .add_submessages(sub_messages)
+    )
 }
 
-pub fn execute_sync_limit(
+pub fn execute_decrease_limit(
     deps: DepsMut,
-    caller: Addr, 
-    address: Option<Addr>,
+    address: Addr,
     id: u64,
-    force_finish: bool
+    amount: Uint128
 ) -> Result<Response, ContractError> {
-
-    let real_address;
-    match address {
-        Some(addr) => {real_address = addr.clone();},
-        None => {real_address = caller.clone();}
+    let (list, _max_number) = LIMIT_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
+    if !list.contains(&id) {
+        return Err(ContractError::OrderNotExist {});
+    }
+    let mut limit_config = limit_orders().load(deps.storage, (address.clone(), id))?;
+    if limit_config.finished {
+        return Err(ContractError::AlreadyFinishedOrder {});
     }
 
-    if real_address != caller.clone() {
-        util::check_owner(deps.storage, caller.clone())?;
+    // token1 is taken out while waiting for the entry, token2 once bought, Stop takes everything
+    let denom;
+    if limit_config.phase == LimitPhase::Entry {
+        if amount.is_zero() || amount >= limit_config.token1_amount {
+            return Err(ContractError::InsufficientOrderAmount {});
+        }
+        denom = limit_config.msg.token1_denom.clone();
+        limit_config.token1_amount -= amount;
+        limit_config.initial_token1_amount -= amount;
+    } else {
+        if amount.is_zero() || amount >= limit_config.token2_amount {
+            return Err(ContractError::InsufficientOrderAmount {});
+        }
+        // the deposit shrinks in proportion, avg_buy_price and the targets stay
+        denom = limit_config.token2_denom.clone();
+        limit_config.initial_token1_amount -= limit_config.initial_token1_amount * amount / limit_config.token2_amount;
+        limit_config.token2_amount -= amount;
     }
-    
-    let (mut list, _max_number) = LIMIT_ORDERS_COUNT.load(deps.storage, real_address.clone()).unwrap_or((vec![], 0));
+    limit_orders().save(deps.storage, (address.clone(), id), &limit_config)?;
+    util::decrease_escrow(deps.storage, &denom, amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "decrease_limit")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_attribute("amount", amount)
+        .add_message(util::transfer_token_message(deps.querier, denom, amount, address.clone())?)
+    )
+}
+
+pub fn execute_amend_limit(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64,
+    amendment: LimitAmendment
+) -> Result<Response, ContractError> {
+    let (list, _max_number) = LIMIT_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
     if !list.contains(&id) {
         return Err(ContractError::OrderNotExist {});
     }
-    
-    let mut limit_config = LIMIT_ORDERS.load(deps.storage, (real_address.clone(), id))?;
-    
+    let mut limit_config = limit_orders().load(deps.storage, (address.clone(), id))?;
     if limit_config.finished {
         return Err(ContractError::AlreadyFinishedOrder {});
     }
-    let (swap_amount, _other_denom, _message) = util::get_swap_amount_and_denom_and_message(deps.querier, limit_config.msg.pool_address.clone(), limit_config.msg.token1_denom.clone(), limit_config.initial_token1_amount)?;
 
-    let current_buy_price = limit_config.initial_token1_amount * util::decimal() / swap_amount;
+    let mut event = Event::new("amend_order")
+        .add_attribute("order_type", "limit")
+        .add_attribute("id", id.to_string());
+    if let Some(take_profit_percentage) = amendment.take_profit_percentage {
+        limit_config.msg.take_profit_percentage = take_profit_percentage;
+        event = event.add_attribute("take_profit_percentage", take_profit_percentage.to_string());
+    }
+    if let Some(stop_loss_percentage) = amendment.stop_loss_percentage {
+        limit_config.msg.stop_loss_percentage = Some(stop_loss_percentage);
+        event = event.add_attribute("stop_loss_percentage", stop_loss_percentage.to_string());
+    }
+    if let Some(trail_percentage) = amendment.trail_percentage {
+        limit_config.msg.trail_percentage = Some(trail_percentage);
+        event = event.add_attribute("trail_percentage", trail_percentage.to_string());
+    }
+    if let Some(max_slippage_bps) = amendment.max_slippage_bps {
+        limit_config.msg.max_slippage_bps = Some(util::check_max_slippage(max_slippage_bps)?);
+        event = event.add_attribute("max_slippage_bps", max_slippage_bps.to_string());
+    }
+    if let Some(expires_at) = amendment.expires_at {
+        util::check_expires_at(&env, Some(expires_at))?;
+        limit_config.msg.expires_at = Some(expires_at);
+        event = event.add_attribute("expires_at", expires_at.to_string());
+    }
 
-    // return Err(ContractError::DebugValue { value: current_buy_price});
+    // the targets are set again from the stored avg_buy_price, a trailing order keeps its peak
+    let avg_buy_price = limit_config.avg_buy_price;
+    let peak_price = limit_config.peak_price;
+    set_targets(&mut limit_config, avg_buy_price)?;
+    if let (Some(trail_percentage), Some(peak_price)) = (limit_config.msg.trail_percentage, peak_price) {
+        let peak_price = peak_price.max(avg_buy_price);
+        limit_config.peak_price = Some(peak_price);
+        limit_config.trail_trigger_price = Some(get_trail_trigger_price(peak_price, trail_percentage));
+    }
+    limit_orders().save(deps.storage, (address.clone(), id), &limit_config)?;
 
-    if current_buy_price > limit_config.target_buy_price || force_finish {
-        let (index, _max_number) = list.iter().enumerate().find(|(_i, c)| c == &&id).unwrap_or((0, &0));
-        list.remove(index);
-        let mut messages: Vec<CosmosMsg> = vec![];
-        
-        let (swap_amount, _origin_denom, messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, limit_config.msg.pool_address.clone(), limit_config.token2_denom.clone(), limit_config.token2_amount)?;
-        
-        for i in 0..messages_swap.len() {
-            messages.push(messages_swap[i].clone());
-        }
+    Ok(Response::new()
+        .add_attribute("action", "amend_limit")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_event(event)
+    )
+}
+
+pub fn execute_stop_limit(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64
+) -> Result<Response, ContractError> {
+    Ok(execute_sync_limit(deps, env, address.clone(), Some(address.clone()), id, true)?)
+}
+
+pub fn execute_sync_limit(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    address: Option<Addr>,
+    id: u64,
+    force_finish: bool
+) -> Result<Response, ContractError> {
+    sync_order::<LimitConfig>(deps, env, caller, address, id, force_finish)
+}
 
-        //transfer to sender
-        messages.push(util::transfer_token_message(deps.querier, limit_config.msg.token1_denom.clone(), swap_amount, real_address.clone())?);
+pub fn reply_swap_limit(
+    deps: DepsMut,
+    pending: PendingSwap,
+    amount: Uint128
+) -> Result<(Response, Vec<Transfer>), ContractError> {
+    let mut limit_config = limit_orders().load(deps.storage, (pending.address.clone(), pending.id))?;
+    let mut transfers: Vec<Transfer> = vec![];
+    let mut keeper_tip = Uint128::zero();
 
-        limit_config.finished = true;
-        LIMIT_ORDERS.save(deps.storage, (real_address.clone(), id), &limit_config)?;
+    match pending.kind {
+        SwapKind::Buy => {
+            //the tip was kept back in token1 when the entry was bought
+            keeper_tip = limit_config.token1_amount;
+            limit_config.token1_amount = Uint128::zero();
+            limit_config.token2_amount = amount;
+            transfers.push((limit_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+        },
+        SwapKind::Finish => {
+            //pay the keeper and transfer the rest to sender
+            keeper_tip = util::get_keeper_tip(deps.storage, &pending.keeper, &pending.address, amount)?;
+            limit_config.token1_amount = amount - keeper_tip;
+            limit_config.token2_amount = Uint128::zero();
 
-        return Ok(Response::new()
-            .add_attribute("action", "sync_limit_success")
-            .add_attribute("sender", real_address.to_string())
-            .add_attribute("id", id.to_string())
-            .add_messages(messages)
-        );
-    } else {
-        return Ok(Response::new()
-            .add_attribute("action", "sync_limit_waiting")
-            .add_attribute("sender", real_address.to_string())
-            .add_attribute("id", id.to_string())
-        );
+            //performance fee on the realized profit
+            let (fee, fee_recipient) = util::take_performance_fee(deps.storage, &limit_config.msg.token1_denom, limit_config.initial_token1_amount, limit_config.token1_amount)?;
+            limit_config.token1_amount -= fee;
+            limit_config.stats.performance_fees += fee;
+            util::add_realized_pnl(&mut limit_config.stats, limit_config.initial_token1_amount, limit_config.token1_amount);
+
+            transfers.push((limit_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+            transfers.push((limit_config.msg.token1_denom.clone(), fee, fee_recipient));
+            transfers.push((limit_config.msg.token1_denom.clone(), limit_config.token1_amount, pending.address.clone()));
+        },
+        SwapKind::Increase => {
+            limit_config.token2_amount += amount;
+            let avg_buy_price = limit_config.initial_token1_amount * util::decimal() / limit_config.token2_amount;
+            set_targets(&mut limit_config, avg_buy_price)?;
+        },
+        _ => {
+            limit_config.token2_amount = amount;
+        }
     }
-    
+    limit_config.stats.keeper_fees += keeper_tip;
+    limit_orders().save(deps.storage, (pending.address.clone(), pending.id), &limit_config)?;
+    if limit_config.finished {
+        archive_order(deps.storage, OrderType::Limit, &pending.address, pending.id)?;
+    }
+
+    Ok((Response::new()
+        .add_attribute("action", "reply_swap_limit")
+        .add_attribute("sender", pending.address.to_string())
+        .add_attribute("id", pending.id.to_string())
+        .add_attribute("keeper", pending.keeper.to_string())
+        .add_attribute("keeper_tip", keeper_tip), transfers))
 }
@@ -1,17 +1,796 @@
 mod limit;
 mod smart;
 mod grid;
+mod dca;
+mod twap;
+mod bracket;
 
 pub use limit::execute_start_limit as start_limit;
 pub use limit::execute_stop_limit as stop_limit;
 pub use limit::execute_sync_limit as sync_limit;
+pub use limit::query_simulate_limit as simulate_limit;
+pub use limit::execute_increase_limit as increase_limit;
+pub use limit::execute_decrease_limit as decrease_limit;
+pub use limit::execute_amend_limit as amend_limit;
 
 pub use smart::execute_start_smart as start_smart;
 pub use smart::execute_stop_smart as stop_smart;
 pub use smart::execute_sync_smart as sync_smart;
+pub use smart::query_simulate_smart as simulate_smart;
+pub use smart::execute_increase_smart as increase_smart;
+pub use smart::execute_decrease_smart as decrease_smart;
+pub use smart::execute_amend_smart as amend_smart;
+pub use smart::get_deposit_amount as smart_deposit_amount;
 
 pub use grid::execute_start_grid as start_grid;
 pub use grid::execute_stop_grid as stop_grid;
 pub use grid::execute_sync_grid as sync_grid;
+pub use grid::query_simulate_grid as simulate_grid;
+pub use grid::execute_increase_grid as increase_grid;
+pub use grid::execute_decrease_grid as decrease_grid;
+pub use grid::execute_amend_grid as amend_grid;
 
+pub use dca::execute_start_dca as start_dca;
+pub use dca::execute_stop_dca as stop_dca;
+pub use dca::execute_sync_dca as sync_dca;
 
+pub use twap::execute_start_twap as start_twap;
+pub use twap::execute_stop_twap as stop_twap;
+pub use twap::execute_sync_twap as sync_twap;
+
+pub use bracket::execute_start_bracket as start_bracket;
+pub use bracket::execute_stop_bracket as stop_bracket;
+pub use bracket::execute_sync_bracket as sync_bracket;
+
+use cosmwasm_std::{
+    Deps, DepsMut, Env, Response, Uint128, Addr, CosmosMsg, SubMsg, Storage, QuerierWrapper, Order, StdError, StdResult, Attribute
+};
+use cw_storage_plus::Map;
+use std::convert::TryFrom;
+use cw20::{Balance, Denom};
+use crate::error::ContractError;
+use crate::msg::{
+    OrderFill, FillDirection, OrderType, OrderConfig, DexKind, LimitConfig, SmartConfig, GridConfig, DcaConfig, TwapConfig, BracketConfig
+};
+use crate::state::{
+    CONFIG, PendingSwap, SwapKind, PENDING_SWAPS, ORDER_FILLS, ESCROWED,
+    LIMIT_ORDERS_COUNT, SMART_ORDERS_COUNT, GRID_ORDERS_COUNT, DCA_ORDERS_COUNT, TWAP_ORDERS_COUNT, BRACKET_ORDERS_COUNT,
+    LIMIT_ORDERS_ARCHIVE, SMART_ORDERS_ARCHIVE, GRID_ORDERS_ARCHIVE, DCA_ORDERS_ARCHIVE, TWAP_ORDERS_ARCHIVE, BRACKET_ORDERS_ARCHIVE,
+    limit_orders, smart_orders, grid_orders, dca_orders, twap_orders, bracket_orders
+};
+
+use crate::util;
+
+// Token transfer made once a swap is settled: (denom, amount, receiver)
+pub type Transfer = (Denom, Uint128, Addr);
+
+// Queue the swap and dispatch it so that the reply can measure the output actually received
+pub fn queue_swap(
+    storage: &mut dyn Storage,
+    querier: QuerierWrapper,
+    contract_address: Addr,
+    mut pending: PendingSwap,
+    messages: Vec<CosmosMsg>
+) -> Result<Vec<SubMsg>, ContractError> {
+    let last = PENDING_SWAPS.keys(storage, None, None, Order::Descending).next().transpose()?;
+    let seq = last.map(|seq| seq + 1).unwrap_or(0u64);
+
+    pending.balance_before = util::get_token_amount(querier, pending.output_denom.clone(), contract_address)?;
+    PENDING_SWAPS.save(storage, seq, &pending)?;
+
+    // only the swap itself, the last message, needs a reply
+    let mut sub_messages: Vec<SubMsg> = messages.into_iter().map(SubMsg::new).collect();
+    if let Some(swap) = sub_messages.pop() {
+        sub_messages.push(SubMsg::reply_on_success(swap.msg, util::SWAP_REPLY_ID));
+    }
+    Ok(sub_messages)
+}
+
+// What a strategy wants done on a start, a price check or a stop
+pub struct Transition {
+    pub action: &'static str,
+    // token1 for Start, Buy and Increase swaps, token2 for Sell and Finish swaps
+    pub swap: Option<(SwapKind, Uint128)>,
+    // limit set by the user, token1 per token2, the swap fills at this price or better on top of the slippage bound
+    pub limit_price: Option<Uint128>,
+    // token1 given back to the owner
+    pub refund: Uint128,
+    // token2 given back to the owner
+    pub refund_token2: Uint128,
+    pub attributes: Vec<Attribute>
+}
+
+impl Transition {
+    pub fn new(action: &'static str) -> Self {
+        Transition {
+            action,
+            swap: None,
+            limit_price: None,
+            refund: Uint128::zero(),
+            refund_token2: Uint128::zero(),
+            attributes: vec![]
+        }
+    }
+
+    pub fn swap(mut self, kind: SwapKind, amount: Uint128) -> Self {
+        self.swap = Some((kind, amount));
+        self
+    }
+
+    pub fn at_limit(mut self, price: Uint128) -> Self {
+        self.limit_price = Some(price);
+        self
+    }
+
+    pub fn refund(mut self, amount: Uint128) -> Self {
+        self.refund = amount;
+        self
+    }
+
+    pub fn refund_token2(mut self, amount: Uint128) -> Self {
+        self.refund_token2 = amount;
+        self
+    }
+
+    pub fn add_attribute(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.attributes.push(Attribute::new(key, value));
+        self
+    }
+}
+
+// Expected pool output at the current price, without any slippage
+pub trait Quote {
+    // token2 received for token1_amount
+    fn buy(&self, token1_amount: Uint128) -> Result<Uint128, ContractError>;
+    // token1 received for token2_amount
+    fn sell(&self, token2_amount: Uint128) -> Result<Uint128, ContractError>;
+}
+
+pub struct PoolQuote<'a> {
+    querier: QuerierWrapper<'a>,
+    dex: DexKind,
+    pool_address: Addr,
+    token1_denom: Denom,
+    token2_denom: Denom
+}
+
+impl<'a> Quote for PoolQuote<'a> {
+    fn buy(&self, token1_amount: Uint128) -> Result<Uint128, ContractError> {
+        let (token2_amount, _other_denom, _messages) = util::get_swap_amount_and_denom_and_message(self.querier, self.dex, self.pool_address.clone(), self.token1_denom.clone(), token1_amount, None, 0u64)?;
+        Ok(token2_amount)
+    }
+
+    fn sell(&self, token2_amount: Uint128) -> Result<Uint128, ContractError> {
+        let (token1_amount, _other_denom, _messages) = util::get_swap_amount_and_denom_and_message(self.querier, self.dex, self.pool_address.clone(), self.token2_denom.clone(), token2_amount, None, 0u64)?;
+        Ok(token1_amount)
+    }
+}
+
+// The parts of an order message the driver needs
+pub struct OrderTerms {
+    pub token1_denom: Denom,
+    pub pool_address: Addr,
+    pub max_slippage_bps: Option<u64>,
+    pub expires_at: Option<u64>
+}
+
+// An order strategy only decides what to do, storage, pricing and messages are left to the driver below
+pub trait OrderStrategy: Sized + Clone + Into<OrderConfig> + TryFrom<OrderConfig, Error = StdError> {
+    type Msg;
+    const ORDER_TYPE: OrderType;
+
+    fn terms(msg: &Self::Msg) -> OrderTerms;
+    fn msg(&self) -> &Self::Msg;
+    fn token2_denom(&self) -> Denom;
+    fn dex(&self) -> DexKind;
+    fn is_finished(&self) -> bool;
+
+    // The order a deposit of token1_amount starts with, on a pool of the given adapter, now is the block time in seconds
+    fn on_start(msg: Self::Msg, token1_amount: Uint128, token2_denom: Denom, dex: DexKind, quote: &dyn Quote, now: u64) -> Result<(Self, Transition), ContractError>;
+    // Called on every sync, keeper_fee_bps is zero when the owner syncs
+    fn on_price(&mut self, quote: &dyn Quote, keeper_fee_bps: u64, now: u64) -> Result<Transition, ContractError>;
+    // Close the order on a stop or once it expired
+    fn on_stop(&mut self, quote: &dyn Quote) -> Result<Transition, ContractError>;
+}
+
+fn pool_quote<'a, S: OrderStrategy>(
+    querier: QuerierWrapper<'a>,
+    dex: DexKind,
+    msg: &S::Msg,
+    token2_denom: Denom
+) -> PoolQuote<'a> {
+    let terms = S::terms(msg);
+    PoolQuote {
+        querier,
+        dex,
+        pool_address: terms.pool_address,
+        token1_denom: terms.token1_denom,
+        token2_denom
+    }
+}
+
+// Check the message and let the strategy set up the order, nothing is stored
+fn open_order<S: OrderStrategy>(
+    deps: Deps,
+    env: &Env,
+    msg: S::Msg,
+    token1_amount: Uint128
+) -> Result<(S, Transition), ContractError> {
+    let terms = S::terms(&msg);
+    let dex = util::check_token_and_pool(deps.storage, deps.querier, terms.token1_denom.clone(), terms.pool_address.clone())?;
+    util::get_max_slippage(deps.storage, terms.max_slippage_bps)?;
+    util::check_expires_at(env, terms.expires_at)?;
+
+    let (_token2_amount, token2_denom, _messages) = util::get_swap_amount_and_denom_and_message(deps.querier, dex, terms.pool_address.clone(), terms.token1_denom.clone(), token1_amount, None, 0u64)?;
+    let quote = pool_quote::<S>(deps.querier, dex, &msg, token2_denom.clone());
+    S::on_start(msg, token1_amount, token2_denom, dex, &quote, env.block.time.seconds())
+}
+
+pub fn simulate_order<S: OrderStrategy>(
+    deps: Deps,
+    env: Env,
+    msg: S::Msg,
+    amount: Uint128
+) -> Result<(S, Transition), ContractError> {
+    open_order::<S>(deps, &env, msg, amount)
+}
+
+// Queue the swap the strategy asked for, if any, the reply settles it
+fn queue_transition_swap<S: OrderStrategy>(
+    deps: &mut DepsMut,
+    env: &Env,
+    order: &S,
+    address: &Addr,
+    id: u64,
+    keeper: &Addr,
+    transition: &Transition
+) -> Result<Vec<SubMsg>, ContractError> {
+    let (kind, amount) = match transition.swap.clone() {
+        Some(swap) => swap,
+        None => return Ok(vec![])
+    };
+    let terms = S::terms(order.msg());
+    let max_slippage_bps = util::get_max_slippage(deps.storage, terms.max_slippage_bps)?;
+    let (input_denom, output_denom, input_is_token1) = match kind {
+        SwapKind::Start | SwapKind::Buy | SwapKind::Increase => (terms.token1_denom, order.token2_denom(), true),
+        SwapKind::Sell | SwapKind::Finish => (order.token2_denom(), terms.token1_denom, false)
+    };
+    let limit_output = transition.limit_price
+        .filter(|price| !price.is_zero())
+        .map(|price| util::get_output_at_price(amount, price, input_is_token1));
+    let (_swap_amount, _other_denom, messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, order.dex(), terms.pool_address, input_denom.clone(), amount, limit_output, max_slippage_bps)?;
+
+    queue_swap(deps.storage, deps.querier, env.contract.address.clone(), PendingSwap {
+        order_type: S::ORDER_TYPE,
+        address: address.clone(),
+        id,
+        keeper: keeper.clone(),
+        kind,
+        input_amount: amount,
+        input_denom,
+        output_denom,
+        balance_before: Uint128::zero()
+    }, messages_swap)
+}
+
+// Carry out a transition: refunds, swap, save, and archive the order once it is settled
+fn apply_transition<S: OrderStrategy>(
+    deps: &mut DepsMut,
+    env: &Env,
+    order: &S,
+    address: &Addr,
+    id: u64,
+    keeper: &Addr,
+    transition: &Transition
+) -> Result<(Vec<CosmosMsg>, Vec<SubMsg>), ContractError> {
+    let terms = S::terms(order.msg());
+
+    // the swap goes first, a price out of tolerance fails before anything is written
+    let sub_messages = queue_transition_swap(deps, env, order, address, id, keeper, transition)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !transition.refund.is_zero() {
+        util::decrease_escrow(deps.storage, &terms.token1_denom, transition.refund)?;
+        messages.push(util::transfer_token_message(deps.querier, terms.token1_denom.clone(), transition.refund, address.clone())?);
+    }
+    if !transition.refund_token2.is_zero() {
+        util::decrease_escrow(deps.storage, &order.token2_denom(), transition.refund_token2)?;
+        messages.push(util::transfer_token_message(deps.querier, order.token2_denom(), transition.refund_token2, address.clone())?);
+    }
+
+    save_order(deps.storage, address, id, &order.clone().into())?;
+    // with a swap queued the reply archives it
+    if order.is_finished() && transition.swap.is_none() {
+        archive_order(deps.storage, S::ORDER_TYPE, address, id)?;
+    }
+    Ok((messages, sub_messages))
+}
+
+pub fn start_order<S: OrderStrategy>(
+    mut deps: DepsMut,
+    env: Env,
+    msg: S::Msg,
+    balance: Balance,
+    address: Addr
+) -> Result<Response, ContractError> {
+    if balance.is_empty() {
+        return Err(ContractError::EmptyBalance {});
+    }
+    let count = orders_count(S::ORDER_TYPE);
+    let (mut list, max_number) = count.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
+
+    if list.len() as u64 >= util::MAX_ORDER {
+        return Err(ContractError::MaxOrderCountExceed {});
+    }
+
+    let token1_denom = S::terms(&msg).token1_denom;
+    let token1_amount = util::get_amount_of_denom(balance, token1_denom.clone())?;
+    let (order, transition) = open_order::<S>(deps.as_ref(), &env, msg, token1_amount)?;
+
+    list.push(max_number);
+    count.save(deps.storage, address.clone(), &(list, max_number + 1))?;
+    util::increase_escrow(deps.storage, &token1_denom, token1_amount)?;
+
+    let (messages, sub_messages) = apply_transition(&mut deps, &env, &order, &address, max_number, &address, &transition)?;
+
+    let mut action = format!("start_{}", S::ORDER_TYPE);
+    if !transition.action.is_empty() {
+        action = format!("{}_{}", action, transition.action);
+    }
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("address", address.to_string())
+        .add_attributes(transition.attributes)
+        .add_messages(messages)
+        .add_submessages(sub_messages)
+    )
+}
+
+pub fn sync_order<S: OrderStrategy>(
+    mut deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    address: Option<Addr>,
+    id: u64,
+    force_finish: bool
+) -> Result<Response, ContractError> {
+    let real_address = address.unwrap_or_else(|| caller.clone());
+
+    let (list, _max_number) = orders_count(S::ORDER_TYPE).load(deps.storage, real_address.clone()).unwrap_or((vec![], 0));
+    if !list.contains(&id) {
+        return Err(ContractError::OrderNotExist {});
+    }
+    let mut order = S::try_from(load_active_order(deps.storage, S::ORDER_TYPE, &real_address, id)?)?;
+    if order.is_finished() {
+        return Err(ContractError::AlreadyFinishedOrder {});
+    }
+
+    let terms = S::terms(order.msg());
+    util::get_max_slippage(deps.storage, terms.max_slippage_bps)?;
+    let quote = pool_quote::<S>(deps.querier, order.dex(), order.msg(), order.token2_denom());
+
+    // An expired order is closed the same way as a stop
+    let expired = util::is_expired(&env, terms.expires_at);
+    let mut transition = if force_finish || expired {
+        order.on_stop(&quote)?
+    } else {
+        let mut keeper_fee_bps = 0u64;
+        if caller != real_address {
+            keeper_fee_bps = CONFIG.load(deps.storage)?.keeper_fee_bps;
+        }
+        order.on_price(&quote, keeper_fee_bps, env.block.time.seconds())?
+    };
+    if expired {
+        transition.action = "expired";
+    }
+
+    let (messages, sub_messages) = apply_transition(&mut deps, &env, &order, &real_address, id, &caller, &transition)?;
+
+    Ok(Response::new()
+        .add_attribute("action", format!("sync_{}_{}", S::ORDER_TYPE, transition.action))
+        .add_attribute("sender", real_address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_attributes(transition.attributes)
+        .add_messages(messages)
+        .add_submessages(sub_messages)
+    )
+}
+
+// Ids of the active orders of each type, with the next id to use
+pub fn orders_count(
+    order_type: OrderType
+) -> Map<'static, Addr, (Vec<u64>, u64)> {
+    match order_type {
+        OrderType::Limit => LIMIT_ORDERS_COUNT,
+        OrderType::Smart => SMART_ORDERS_COUNT,
+        OrderType::Grid => GRID_ORDERS_COUNT,
+        OrderType::Dca => DCA_ORDERS_COUNT,
+        OrderType::Twap => TWAP_ORDERS_COUNT,
+        OrderType::Bracket => BRACKET_ORDERS_COUNT
+    }
+}
+
+// Move a settled order to the archive, its slot no longer counts toward MAX_ORDER
+pub fn archive_order(
+    storage: &mut dyn Storage,
+    order_type: OrderType,
+    address: &Addr,
+    id: u64
+) -> Result<(), ContractError> {
+    let key = (address.clone(), id);
+    match order_type {
+        OrderType::Limit => {
+            let order = limit_orders().load(storage, key.clone())?;
+            limit_orders().remove(storage, key.clone())?;
+            LIMIT_ORDERS_ARCHIVE.save(storage, key, &order)?;
+        },
+        OrderType::Smart => {
+            let order = smart_orders().load(storage, key.clone())?;
+            smart_orders().remove(storage, key.clone())?;
+            SMART_ORDERS_ARCHIVE.save(storage, key, &order)?;
+        },
+        OrderType::Grid => {
+            let order = grid_orders().load(storage, key.clone())?;
+            grid_orders().remove(storage, key.clone())?;
+            GRID_ORDERS_ARCHIVE.save(storage, key, &order)?;
+        },
+        OrderType::Dca => {
+            let order = dca_orders().load(storage, key.clone())?;
+            dca_orders().remove(storage, key.clone())?;
+            DCA_ORDERS_ARCHIVE.save(storage, key, &order)?;
+        },
+        OrderType::Twap => {
+            let order = twap_orders().load(storage, key.clone())?;
+            twap_orders().remove(storage, key.clone())?;
+            TWAP_ORDERS_ARCHIVE.save(storage, key, &order)?;
+        },
+        OrderType::Bracket => {
+            let order = bracket_orders().load(storage, key.clone())?;
+            bracket_orders().remove(storage, key.clone())?;
+            BRACKET_ORDERS_ARCHIVE.save(storage, key, &order)?;
+        }
+    }
+
+    let count = orders_count(order_type);
+    let (mut list, max_number) = count.load(storage, address.clone())?;
+    list.retain(|i| *i != id);
+    count.save(storage, address.clone(), &(list, max_number))?;
+    Ok(())
+}
+
+impl From<LimitConfig> for OrderConfig {
+    fn from(order: LimitConfig) -> Self {
+        OrderConfig::Limit(order)
+    }
+}
+
+impl TryFrom<OrderConfig> for LimitConfig {
+    type Error = StdError;
+
+    fn try_from(order: OrderConfig) -> StdResult<Self> {
+        match order {
+            OrderConfig::Limit(order) => Ok(order),
+            _ => Err(StdError::generic_err("Not a limit order"))
+        }
+    }
+}
+
+impl From<SmartConfig> for OrderConfig {
+    fn from(order: SmartConfig) -> Self {
+        OrderConfig::Smart(order)
+    }
+}
+
+impl TryFrom<OrderConfig> for SmartConfig {
+    type Error = StdError;
+
+    fn try_from(order: OrderConfig) -> StdResult<Self> {
+        match order {
+            OrderConfig::Smart(order) => Ok(order),
+            _ => Err(StdError::generic_err("Not a smart order"))
+        }
+    }
+}
+
+impl From<GridConfig> for OrderConfig {
+    fn from(order: GridConfig) -> Self {
+        OrderConfig::Grid(order)
+    }
+}
+
+impl TryFrom<OrderConfig> for GridConfig {
+    type Error = StdError;
+
+    fn try_from(order: OrderConfig) -> StdResult<Self> {
+        match order {
+            OrderConfig::Grid(order) => Ok(order),
+            _ => Err(StdError::generic_err("Not a grid order"))
+        }
+    }
+}
+
+impl From<DcaConfig> for OrderConfig {
+    fn from(order: DcaConfig) -> Self {
+        OrderConfig::Dca(order)
+    }
+}
+
+impl TryFrom<OrderConfig> for DcaConfig {
+    type Error = StdError;
+
+    fn try_from(order: OrderConfig) -> StdResult<Self> {
+        match order {
+            OrderConfig::Dca(order) => Ok(order),
+            _ => Err(StdError::generic_err("Not a dca order"))
+        }
+    }
+}
+
+impl From<TwapConfig> for OrderConfig {
+    fn from(order: TwapConfig) -> Self {
+        OrderConfig::Twap(order)
+    }
+}
+
+impl TryFrom<OrderConfig> for TwapConfig {
+    type Error = StdError;
+
+    fn try_from(order: OrderConfig) -> StdResult<Self> {
+        match order {
+            OrderConfig::Twap(order) => Ok(order),
+            _ => Err(StdError::generic_err("Not a twap order"))
+        }
+    }
+}
+
+impl From<BracketConfig> for OrderConfig {
+    fn from(order: BracketConfig) -> Self {
+        OrderConfig::Bracket(order)
+    }
+}
+
+impl TryFrom<OrderConfig> for BracketConfig {
+    type Error = StdError;
+
+    fn try_from(order: OrderConfig) -> StdResult<Self> {
+        match order {
+            OrderConfig::Bracket(order) => Ok(order),
+            _ => Err(StdError::generic_err("Not a bracket order"))
+        }
+    }
+}
+
+// The active order only
+pub fn load_active_order(
+    storage: &dyn Storage,
+    order_type: OrderType,
+    address: &Addr,
+    id: u64
+) -> StdResult<OrderConfig> {
+    let key = (address.clone(), id);
+    Ok(match order_type {
+        OrderType::Limit => OrderConfig::Limit(limit_orders().load(storage, key)?),
+        OrderType::Smart => OrderConfig::Smart(smart_orders().load(storage, key)?),
+        OrderType::Grid => OrderConfig::Grid(grid_orders().load(storage, key)?),
+        OrderType::Dca => OrderConfig::Dca(dca_orders().load(storage, key)?),
+        OrderType::Twap => OrderConfig::Twap(twap_orders().load(storage, key)?),
+        OrderType::Bracket => OrderConfig::Bracket(bracket_orders().load(storage, key)?)
+    })
+}
+
+// Save an active order in the map of its type
+pub fn save_order(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    id: u64,
+    order: &OrderConfig
+) -> StdResult<()> {
+    let key = (address.clone(), id);
+    match order {
+        OrderConfig::Limit(order) => limit_orders().save(storage, key, order),
+        OrderConfig::Smart(order) => smart_orders().save(storage, key, order),
+        OrderConfig::Grid(order) => grid_orders().save(storage, key, order),
+        OrderConfig::Dca(order) => dca_orders().save(storage, key, order),
+        OrderConfig::Twap(order) => twap_orders().save(storage, key, order),
+        OrderConfig::Bracket(order) => bracket_orders().save(storage, key, order)
+    }
+}
+
+// The order, active or archived
+pub fn load_order(
+    storage: &dyn Storage,
+    order_type: OrderType,
+    address: &Addr,
+    id: u64
+) -> StdResult<OrderConfig> {
+    let key = (address.clone(), id);
+    Ok(match order_type {
+        OrderType::Limit => OrderConfig::Limit(match limit_orders().may_load(storage, key.clone())? {
+            Some(order) => order,
+            None => LIMIT_ORDERS_ARCHIVE.load(storage, key)?
+        }),
+        OrderType::Smart => OrderConfig::Smart(match smart_orders().may_load(storage, key.clone())? {
+            Some(order) => order,
+            None => SMART_ORDERS_ARCHIVE.load(storage, key)?
+        }),
+        OrderType::Grid => OrderConfig::Grid(match grid_orders().may_load(storage, key.clone())? {
+            Some(order) => order,
+            None => GRID_ORDERS_ARCHIVE.load(storage, key)?
+        }),
+        OrderType::Dca => OrderConfig::Dca(match dca_orders().may_load(storage, key.clone())? {
+            Some(order) => order,
+            None => DCA_ORDERS_ARCHIVE.load(storage, key)?
+        }),
+        OrderType::Twap => OrderConfig::Twap(match twap_orders().may_load(storage, key.clone())? {
+            Some(order) => order,
+            None => TWAP_ORDERS_ARCHIVE.load(storage, key)?
+        }),
+        OrderType::Bracket => OrderConfig::Bracket(match bracket_orders().may_load(storage, key.clone())? {
+            Some(order) => order,
+            None => BRACKET_ORDERS_ARCHIVE.load(storage, key)?
+        })
+    })
+}
+
+fn is_finished(
+    storage: &dyn Storage,
+    order_type: OrderType,
+    address: &Addr,
+    id: u64
+) -> StdResult<bool> {
+    Ok(match load_order(storage, order_type, address, id)? {
+        OrderConfig::Limit(order) => order.finished,
+        OrderConfig::Smart(order) => order.finished,
+        OrderConfig::Grid(order) => order.finished,
+        OrderConfig::Dca(order) => order.finished,
+        OrderConfig::Twap(order) => order.finished,
+        OrderConfig::Bracket(order) => order.finished
+    })
+}
+
+// Archive the finished orders still listed as active, the id lists were not saved on finish before
+pub fn archive_finished_orders(
+    storage: &mut dyn Storage,
+    order_type: OrderType
+) -> Result<u64, ContractError> {
+    let all:StdResult<Vec<_>> = orders_count(order_type)
+        .range(storage, None, None, Order::Ascending)
+        .collect();
+    let mut archived = 0u64;
+    for (address, (list, _max_number)) in all? {
+        for id in list {
+            if is_finished(storage, order_type, &address, id)? {
+                archive_order(storage, order_type, &address, id)?;
+                archived += 1;
+            }
+        }
+    }
+    Ok(archived)
+}
+
+// Token amounts an active order holds in the contract
+fn held_amounts(
+    order: &OrderConfig
+) -> Vec<(Denom, Uint128)> {
+    match order {
+        OrderConfig::Limit(order) => vec![(order.msg.token1_denom.clone(), order.token1_amount), (order.token2_denom.clone(), order.token2_amount)],
+        OrderConfig::Smart(order) => vec![(order.msg.token1_denom.clone(), order.token1_amount), (order.token2_denom.clone(), order.token2_amount)],
+        OrderConfig::Grid(order) => vec![(order.msg.token1_denom.clone(), order.token1_amount), (order.token2_denom.clone(), order.token2_amount)],
+        OrderConfig::Dca(order) => vec![(order.msg.token1_denom.clone(), order.token1_amount), (order.token2_denom.clone(), order.token2_amount)],
+        // every slice is paid out at once, token2_amount only counts them
+        OrderConfig::Twap(order) => vec![(order.msg.token1_denom.clone(), order.token1_amount)],
+        OrderConfig::Bracket(order) => vec![(order.msg.token1_denom.clone(), order.token1_amount), (order.token2_denom.clone(), order.token2_amount)]
+    }
+}
+
+// Set ESCROWED again to what the active orders hold, state saved before the escrow was tracked has none
+pub fn rebuild_escrow(
+    storage: &mut dyn Storage
+) -> Result<(), ContractError> {
+    let keys:StdResult<Vec<_>> = ESCROWED.keys(storage, None, None, Order::Ascending).collect();
+    for key in keys? {
+        ESCROWED.remove(storage, key);
+    }
+
+    for order_type in OrderType::ALL {
+        let all:StdResult<Vec<_>> = orders_count(order_type)
+            .range(storage, None, None, Order::Ascending)
+            .collect();
+        for (address, (list, _max_number)) in all? {
+            for id in list {
+                let order = load_order(storage, order_type, &address, id)?;
+                for (denom, amount) in held_amounts(&order) {
+                    if !amount.is_zero() {
+                        util::increase_escrow(storage, &denom, amount)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Add the settled swap to the order's fill log
+fn record_fill(
+    storage: &mut dyn Storage,
+    env: &Env,
+    pending: &PendingSwap,
+    amount: Uint128
+) -> Result<(), ContractError> {
+    let prefix = ((pending.address.clone(), pending.order_type.key()), pending.id);
+    let last = ORDER_FILLS.prefix(prefix.clone()).keys(storage, None, None, Order::Descending).next().transpose()?;
+    let seq = last.map(|seq| seq + 1).unwrap_or(0u64);
+
+    // start and buy swaps spend token1, sell and finish swaps spend token2
+    let (direction, token1_amount, token2_amount) = match pending.kind {
+        SwapKind::Start | SwapKind::Buy | SwapKind::Increase => (FillDirection::Buy, pending.input_amount, amount),
+        SwapKind::Sell | SwapKind::Finish => (FillDirection::Sell, amount, pending.input_amount)
+    };
+    let mut price = Uint128::zero();
+    if !token2_amount.is_zero() {
+        price = token1_amount * util::decimal() / token2_amount;
+    }
+
+    ORDER_FILLS.save(storage, (prefix.0, prefix.1, seq), &OrderFill {
+        seq,
+        timestamp: env.block.time.seconds(),
+        direction,
+        amount_in: pending.input_amount,
+        amount_out: amount,
+        price
+    })?;
+    Ok(())
+}
+
+pub fn reply_swap(
+    mut deps: DepsMut,
+    env: Env
+) -> Result<Response, ContractError> {
+    // swaps run and reply in the order they were queued
+    let (seq, pending) = PENDING_SWAPS.range(deps.storage, None, None, Order::Ascending).next()
+        .ok_or(ContractError::NoPendingSwap {})??;
+    PENDING_SWAPS.remove(deps.storage, seq);
+
+    let balance = util::get_token_amount(deps.querier, pending.output_denom.clone(), env.contract.address.clone())?;
+    let amount = balance.checked_sub(pending.balance_before).map_err(StdError::from)?;
+
+    // keep the escrow in line with what the swap actually moved
+    util::decrease_escrow(deps.storage, &pending.input_denom, pending.input_amount)?;
+    util::increase_escrow(deps.storage, &pending.output_denom, amount)?;
+    record_fill(deps.storage, &env, &pending, amount)?;
+
+    let (response, transfers) = match pending.order_type {
+        OrderType::Limit => limit::reply_swap_limit(deps.branch(), pending, amount)?,
+        OrderType::Smart => smart::reply_swap_smart(deps.branch(), pending, amount)?,
+        OrderType::Grid => grid::reply_swap_grid(deps.branch(), pending, amount)?,
+        OrderType::Dca => dca::reply_swap_dca(deps.branch(), pending, amount)?,
+        OrderType::Twap => twap::reply_swap_twap(deps.branch(), pending, amount)?,
+        OrderType::Bracket => bracket::reply_swap_bracket(deps.branch(), pending, amount)?
+    };
+
+    // The next queued swap starts from the balance left after the transfers below
+    let next = PENDING_SWAPS.range(deps.storage, None, None, Order::Ascending).next().transpose()?;
+    if let Some((next_seq, mut next)) = next {
+        let mut next_balance = util::get_token_amount(deps.querier, next.output_denom.clone(), env.contract.address.clone())?;
+        for (denom, transfer_amount, _receiver) in transfers.iter() {
+            if denom == &next.output_denom {
+                next_balance -= *transfer_amount;
+            }
+        }
+        next.balance_before = next_balance;
+        PENDING_SWAPS.save(deps.storage, next_seq, &next)?;
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (denom, transfer_amount, receiver) in transfers {
+        if !transfer_amount.is_zero() {
+            util::decrease_escrow(deps.storage, &denom, transfer_amount)?;
+            messages.push(util::transfer_token_message(deps.querier, denom, transfer_amount, receiver)?);
+        }
+    }
+
+    Ok(response
+        .add_attribute("swap_output", amount)
+        .add_messages(messages)
+    )
+}
@@ -1,184 +1,452 @@
 use cosmwasm_std::{
-    DepsMut, Response, Uint128, Addr, CosmosMsg
+    Deps, DepsMut, Env, Response, Uint128, Addr, Event
 };
-use cw20::Balance;
+use cw20::{Balance, Denom};
 use crate::error::ContractError;
 use crate::msg::{
-    SmartMsg, SmartConfig
+    OrderType, DexKind, SmartMsg, SmartConfig, SmartAmendment, SimulateSmartResponse, OrderStats
 };
 use crate::state::{
-    SMART_ORDERS_COUNT, SMART_ORDERS
+    SMART_ORDERS_COUNT, smart_orders, PendingSwap, SwapKind
 };
 
+use crate::ordergroup::{
+    queue_swap, archive_order, start_order, sync_order, simulate_order, Transfer, OrderStrategy, OrderTerms, Quote, Transition
+};
 use crate::util;
 
 pub fn execute_start_smart(
     deps: DepsMut,
+    env: Env,
     msg: SmartMsg,
     balance: Balance,
     address: Addr,
 ) -> Result<Response, ContractError> {
-    if balance.is_empty() {
-        return Err(ContractError::EmptyBalance {});
-    }
-    // Update SMART_ORDERS_COUNT
-    let (mut list, max_number) = SMART_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
-
-    if list.len() as u64 >= util::MAX_ORDER {
-        return Err(ContractError::MaxOrderCountExceed {});
-    }
-
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let _first_token = util::check_token_and_pool(deps.querier, msg.token1_denom.clone(), msg.pool_address.clone())?;
-    let token1_amount = util::get_amount_of_denom(balance, msg.token1_denom.clone())?;
+    start_order::<SmartConfig>(deps, env, msg, balance, address)
+}
 
-    //check if token1_amount is greater than the amount for the total dca steps
+// token1 the order keeps for the initial buy and the whole dca ladder
+pub fn get_deposit_amount(msg: &SmartMsg) -> Uint128 {
     let mut tot_steps = 1u64;
     let mut mul = msg.dca_order_size_multiplier;
     for _i in 0..msg.num_dca_orders {
         tot_steps += mul;
         mul *= mul;
     }
+    Uint128::from(tot_steps) * msg.initial_token1_amount
+}
+
+impl OrderStrategy for SmartConfig {
+    type Msg = SmartMsg;
+    const ORDER_TYPE: OrderType = OrderType::Smart;
+
+    fn terms(msg: &SmartMsg) -> OrderTerms {
+        OrderTerms {
+            token1_denom: msg.token1_denom.clone(),
+            pool_address: msg.pool_address.clone(),
+            max_slippage_bps: msg.max_slippage_bps,
+            expires_at: msg.expires_at
+        }
+    }
+
+    fn msg(&self) -> &SmartMsg {
+        &self.msg
+    }
 
-    if Uint128::from(tot_steps) * msg.initial_token1_amount > token1_amount {
-        return Err(ContractError::InsufficientAmountForSmartOrder {});
-    } else {
-        messages.push(util::transfer_token_message(deps.querier, msg.token1_denom.clone(), token1_amount - Uint128::from(tot_steps) * msg.initial_token1_amount, address.clone())?);
+    fn token2_denom(&self) -> Denom {
+        self.token2_denom.clone()
     }
 
-    // Save current avg_buy_price
-    let (token2_amount, token2_denom, mut messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, msg.pool_address.clone(), msg.token1_denom.clone(), msg.initial_token1_amount)?;
-    messages.append(&mut messages_swap);
+    fn dex(&self) -> DexKind {
+        self.dex
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // The initial swap buys initial_token1_amount, what is left above the dca ladder is refunded
+    fn on_start(
+        msg: SmartMsg,
+        token1_amount: Uint128,
+        token2_denom: Denom,
+        dex: DexKind,
+        quote: &dyn Quote,
+        _now: u64
+    ) -> Result<(Self, Transition), ContractError> {
+        //check if token1_amount is greater than the amount for the total dca steps
+        let deposit_amount = get_deposit_amount(&msg);
+        if deposit_amount > token1_amount {
+            return Err(ContractError::InsufficientAmountForSmartOrder {});
+        }
+
+        // Save current avg_buy_price
+        let token2_amount = quote.buy(msg.initial_token1_amount)?;
+        let avg_buy_price = msg.initial_token1_amount * util::decimal() / token2_amount;
+        let target_buy_price = avg_buy_price * (util::multiple() + Uint128::from(msg.take_profit_percentage)) / util::multiple();
+        let stop_loss_price = util::get_stop_loss_price(avg_buy_price, msg.stop_loss_percentage)?;
 
-    let avg_buy_price = token1_amount * util::decimal() / token2_amount;
-    let target_buy_price = avg_buy_price * (util::multiple() + Uint128::from(msg.take_profit_percentage)) / util::multiple();
+        //make dca_prices, dca_amounts list
+        let dca_prices = get_dca_prices(&msg, avg_buy_price)?;
+        let mut dca_amounts:Vec<Uint128> = vec![];
+        let mut mul_amount = Uint128::from(1u128);
+        for _i in 0..msg.num_dca_orders {
+            mul_amount *= Uint128::from(msg.dca_order_size_multiplier);
+            dca_amounts.push( mul_amount * msg.dca_order_size);
+        }
 
-    list.push(max_number);
-    SMART_ORDERS_COUNT.save(deps.storage, address.clone(), &(list, max_number + 1))?;
+        let initial_token1_amount = msg.initial_token1_amount;
+        let smart_config = SmartConfig {
+            msg,
+            avg_buy_price,
+            target_buy_price,
+            stop_loss_price,
+            deposit_amount,
+            token1_amount: deposit_amount - initial_token1_amount,
+            keeper_tip: Uint128::zero(),
+            token2_amount,
+            token2_denom,
+            dex,
+            finished: false,
+            stats: OrderStats::default(),
+            dca_prices,
+            dca_amounts,
+            current_dca_point: 0u64
+        };
+        check_ladder_funded(&smart_config, smart_config.token1_amount)?;
+        Ok((smart_config, Transition::new("")
+            .refund(token1_amount - deposit_amount)
+            .swap(SwapKind::Start, initial_token1_amount)
+        ))
+    }
 
+    fn on_price(
+        &mut self,
+        quote: &dyn Quote,
+        keeper_fee_bps: u64,
+        _now: u64
+    ) -> Result<Transition, ContractError> {
+        //check the stop loss before buying more, the position is closed anyway
+        let mut stop_loss = false;
+        if let Some(stop_loss_price) = self.stop_loss_price {
+            stop_loss = quote.sell(self.token2_amount)? * util::decimal() / self.token2_amount < stop_loss_price;
+        }
+
+        //check the current_dca_point and do swap Juno->Atom while the current buy price is larger than dca_price
+        //every dca point reached is bought with a single swap
+        let mut dca_amount = Uint128::zero();
+        let mut dca_point = self.current_dca_point;
+        while !stop_loss && dca_point < self.msg.num_dca_orders {
+            let buy_price = self.dca_amounts[dca_point as usize] * util::decimal() / quote.buy(self.dca_amounts[dca_point as usize])?;
+            if buy_price < self.dca_prices[dca_point as usize] {
+                dca_amount += self.dca_amounts[dca_point as usize];
+                dca_point += 1u64;
+            } else {
+                break;
+            }
+        }
+
+        // the bought amount is added in the reply, at no more than the first dca price reached
+        // the keeper tip is kept back from the dca amount, the reply pays exactly this
+        if !dca_amount.is_zero() {
+            let dca_price = self.dca_prices[self.current_dca_point as usize];
+            let keeper_tip = dca_amount * Uint128::from(keeper_fee_bps) / util::bps_multiple();
+            let buy_amount = dca_amount - keeper_tip;
+            self.token1_amount -= buy_amount;
+            self.keeper_tip = keeper_tip;
+            self.current_dca_point = dca_point;
+            return Ok(Transition::new("dca").swap(SwapKind::Buy, buy_amount).at_limit(dca_price));
+        }
+
+        //check if the ATOM->swap rate is larger than avg_buy_price
+        if quote.sell(self.token2_amount)? * util::decimal() / self.token2_amount < self.target_buy_price && !stop_loss {
+            return Ok(Transition::new("waiting"));
+        }
 
-    // Update SMART_ORDERS
-    //make dca_prices, dca_amounts list
+        //the output and the remaining token1 are paid out to sender in the reply
+        //a take profit sells at no less than its target, a stop loss fills at any price
+        self.finished = true;
+        if stop_loss {
+            return Ok(Transition::new("stop_loss").swap(SwapKind::Finish, self.token2_amount));
+        }
+        Ok(Transition::new("success").swap(SwapKind::Finish, self.token2_amount).at_limit(self.target_buy_price))
+    }
+
+    fn on_stop(
+        &mut self,
+        _quote: &dyn Quote
+    ) -> Result<Transition, ContractError> {
+        self.finished = true;
+        Ok(Transition::new("success").swap(SwapKind::Finish, self.token2_amount))
+    }
+}
+
+// The token1 left must still cover every dca point not reached yet
+fn check_ladder_funded(
+    smart_config: &SmartConfig,
+    token1_amount: Uint128
+) -> Result<(), ContractError> {
+    let dca_left: Uint128 = smart_config.dca_amounts.iter().skip(smart_config.current_dca_point as usize).sum();
+    if token1_amount < dca_left {
+        return Err(ContractError::LadderUnderfunded {});
+    }
+    Ok(())
+}
+
+// Price of each dca point, every step further below avg_buy_price
+fn get_dca_prices(
+    msg: &SmartMsg,
+    avg_buy_price: Uint128
+) -> Result<Vec<Uint128>, ContractError> {
     let mut dca_prices:Vec<Uint128> = vec![];
-    let mut dca_amounts:Vec<Uint128> = vec![];
 
     let mut mul_price = 1u64;
-    let mut mul_amount = Uint128::from(1u128);
     let mut start_val = util::multiple();
     for _i in 0..msg.num_dca_orders {
         mul_price *= msg.dca_step_multiplier;
-        dca_prices.push( avg_buy_price * (start_val - Uint128::from(msg.dca_step * mul_price)) / util::multiple() );
-        start_val -= Uint128::from(msg.dca_step * mul_price);
-
-        mul_amount *= Uint128::from(msg.dca_order_size_multiplier);
-        dca_amounts.push( mul_amount * msg.dca_order_size);
-        
-    }
-
-    let smart_config = SmartConfig {
-        msg: msg.clone(),
-        avg_buy_price,
-        target_buy_price,
-        token1_amount: token1_amount - msg.initial_token1_amount,
-        token2_amount,
-        token2_denom,
-        finished: false,
-        dca_prices,
-        dca_amounts,
-        current_dca_point: 0u64
-    };
-
-    SMART_ORDERS.save(deps.storage, (address.clone(), max_number), &smart_config)?;
-    
-    Ok(Response::new()
-        .add_attribute("action", "start_smart")
-        .add_attribute("address", address.clone().to_string())
-        .add_messages(messages)
-    )
+        start_val = start_val.checked_sub(Uint128::from(msg.dca_step * mul_price)).map_err(|_| ContractError::InvalidInput {})?;
+        dca_prices.push( avg_buy_price * start_val / util::multiple() );
+    }
+    Ok(dca_prices)
 }
 
+pub fn query_simulate_smart(
+    deps: Deps,
+    env: Env,
+    msg: SmartMsg,
+    amount: Uint128
+) -> Result<SimulateSmartResponse, ContractError> {
+    let (smart_config, transition) = simulate_order::<SmartConfig>(deps, env, msg, amount)?;
 
+    Ok(SimulateSmartResponse {
+        deposit_amount: smart_config.deposit_amount,
+        refund_amount: transition.refund,
+        swap_amount: smart_config.msg.initial_token1_amount,
+        swap_output: smart_config.token2_amount,
+        smart_order: smart_config
+    })
+}
 
-pub fn execute_stop_smart(
+pub fn execute_increase_smart(
     deps: DepsMut,
+    env: Env,
     address: Addr,
-    id: u64
+    id: u64,
+    balance: Balance
 ) -> Result<Response, ContractError> {
-    Ok(execute_sync_smart(deps, address.clone(), Some(address.clone()), id, true)?)
+    if balance.is_empty() {
+        return Err(ContractError::EmptyBalance {});
+    }
+    let (list, _max_number) = SMART_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
+    if !list.contains(&id) {
+        return Err(ContractError::OrderNotExist {});
+    }
+    let mut smart_config = smart_orders().load(deps.storage, (address.clone(), id))?;
+    if smart_config.finished {
+        return Err(ContractError::AlreadyFinishedOrder {});
+    }
+    // no new funds into a pool that was removed or disabled
+    util::check_pool_registered(deps.storage, smart_config.msg.pool_address.clone())?;
+    check_ladder_funded(&smart_config, smart_config.token1_amount)?;
+    let max_slippage_bps = util::get_max_slippage(deps.storage, smart_config.msg.max_slippage_bps)?;
+    let token1_amount = util::get_amount_of_denom(balance, smart_config.msg.token1_denom.clone())?;
+
+    // the position grows at the current price, avg_buy_price and the targets follow in the reply
+    let (_swap_amount, _other_denom, messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, smart_config.dex, smart_config.msg.pool_address.clone(), smart_config.msg.token1_denom.clone(), token1_amount, None, max_slippage_bps)?;
+    let sub_messages = queue_swap(deps.storage, deps.querier, env.contract.address.clone(), PendingSwap {
+        order_type: OrderType::Smart,
+        address: address.clone(),
+        id,
+        keeper: address.clone(),
+        kind: SwapKind::Increase,
+        input_amount: token1_amount,
+        input_denom: smart_config.msg.token1_denom.clone(),
+        output_denom: smart_config.token2_denom.clone(),
+        balance_before: Uint128::zero()
+    }, messages_swap)?;
+
+    smart_config.deposit_amount += token1_amount;
+    smart_orders().save(deps.storage, (address.clone(), id), &smart_config)?;
+    util::increase_escrow(deps.storage, &smart_config.msg.token1_denom, token1_amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "increase_smart")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_attribute("amount", token1_amount)
+        .add_submessages(sub_messages)
+    )
 }
 
-pub fn execute_sync_smart(
+pub fn execute_decrease_smart(
     deps: DepsMut,
-    caller: Addr, 
-    address: Option<Addr>,
+    address: Addr,
     id: u64,
-    force_finish: bool
+    amount: Uint128
 ) -> Result<Response, ContractError> {
-
-    let real_address;
-    match address {
-        Some(addr) => {real_address = addr.clone();},
-        None => {real_address = caller.clone();}
+    let (list, _max_number) = SMART_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
+    if !list.contains(&id) {
+        return Err(ContractError::OrderNotExist {});
     }
-
-    if real_address != caller.clone() {
-        util::check_owner(deps.storage, caller.clone())?;
+    let mut smart_config = smart_orders().load(deps.storage, (address.clone(), id))?;
+    if smart_config.finished {
+        return Err(ContractError::AlreadyFinishedOrder {});
+    }
+    if amount.is_zero() || amount > smart_config.token1_amount {
+        return Err(ContractError::InsufficientOrderAmount {});
     }
-    
-    let (mut list, _max_number) = SMART_ORDERS_COUNT.load(deps.storage, real_address.clone()).unwrap_or((vec![], 0));
+
+    check_ladder_funded(&smart_config, smart_config.token1_amount - amount)?;
+
+    smart_config.token1_amount -= amount;
+    smart_config.deposit_amount -= amount;
+    smart_orders().save(deps.storage, (address.clone(), id), &smart_config)?;
+    util::decrease_escrow(deps.storage, &smart_config.msg.token1_denom, amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "decrease_smart")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_attribute("amount", amount)
+        .add_message(util::transfer_token_message(deps.querier, smart_config.msg.token1_denom.clone(), amount, address.clone())?)
+    )
+}
+
+pub fn execute_amend_smart(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64,
+    amendment: SmartAmendment
+) -> Result<Response, ContractError> {
+    let (list, _max_number) = SMART_ORDERS_COUNT.load(deps.storage, address.clone()).unwrap_or((vec![], 0));
     if !list.contains(&id) {
         return Err(ContractError::OrderNotExist {});
     }
-    let mut smart_config = SMART_ORDERS.load(deps.storage, (real_address.clone(), id))?;
-
+    let mut smart_config = smart_orders().load(deps.storage, (address.clone(), id))?;
     if smart_config.finished {
         return Err(ContractError::AlreadyFinishedOrder {});
     }
 
-    //check the current_dca_point and do swap Juno->Atom while the current buy price is larger than dca_price
+    let mut event = Event::new("amend_order")
+        .add_attribute("order_type", "smart")
+        .add_attribute("id", id.to_string());
+    if let Some(take_profit_percentage) = amendment.take_profit_percentage {
+        smart_config.msg.take_profit_percentage = take_profit_percentage;
+        event = event.add_attribute("take_profit_percentage", take_profit_percentage.to_string());
+    }
+    if let Some(stop_loss_percentage) = amendment.stop_loss_percentage {
+        smart_config.msg.stop_loss_percentage = Some(stop_loss_percentage);
+        event = event.add_attribute("stop_loss_percentage", stop_loss_percentage.to_string());
+    }
+    if let Some(dca_step) = amendment.dca_step {
+        smart_config.msg.dca_step = dca_step;
+        event = event.add_attribute("dca_step", dca_step.to_string());
+    }
+    if let Some(dca_step_multiplier) = amendment.dca_step_multiplier {
+        smart_config.msg.dca_step_multiplier = dca_step_multiplier;
+        event = event.add_attribute("dca_step_multiplier", dca_step_multiplier.to_string());
+    }
+    if let Some(max_slippage_bps) = amendment.max_slippage_bps {
+        smart_config.msg.max_slippage_bps = Some(util::check_max_slippage(max_slippage_bps)?);
+        event = event.add_attribute("max_slippage_bps", max_slippage_bps.to_string());
+    }
+    if let Some(expires_at) = amendment.expires_at {
+        util::check_expires_at(&env, Some(expires_at))?;
+        smart_config.msg.expires_at = Some(expires_at);
+        event = event.add_attribute("expires_at", expires_at.to_string());
+    }
+
+    // the targets and the dca points not reached yet follow from the stored avg_buy_price
+    smart_config.target_buy_price = smart_config.avg_buy_price * (util::multiple() + Uint128::from(smart_config.msg.take_profit_percentage)) / util::multiple();
+    smart_config.stop_loss_price = util::get_stop_loss_price(smart_config.avg_buy_price, smart_config.msg.stop_loss_percentage)?;
+    smart_config.dca_prices = get_dca_prices(&smart_config.msg, smart_config.avg_buy_price)?;
+    check_ladder_funded(&smart_config, smart_config.token1_amount)?;
+    smart_orders().save(deps.storage, (address.clone(), id), &smart_config)?;
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    while smart_config.current_dca_point < smart_config.msg.num_dca_orders {
-        let (swap_amount, _other_denom, mut messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, smart_config.msg.pool_address.clone(), smart_config.msg.token1_denom.clone(), smart_config.dca_amounts[smart_config.current_dca_point as usize])?;
-        let buy_price = smart_config.dca_amounts[smart_config.current_dca_point as usize] * util::decimal() / swap_amount;
+    Ok(Response::new()
+        .add_attribute("action", "amend_smart")
+        .add_attribute("sender", address.to_string())
+        .add_attribute("id", id.to_string())
+        .add_event(event)
+    )
+}
 
-        if buy_price < smart_config.dca_prices[smart_config.current_dca_point as usize] {
-            // do the swap
-            messages.append(&mut messages_swap);
-    
-            smart_config.token1_amount -= smart_config.dca_amounts[smart_config.current_dca_point as usize];
-            smart_config.token2_amount += swap_amount;
+pub fn execute_stop_smart(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64
+) -> Result<Response, ContractError> {
+    Ok(execute_sync_smart(deps, env, address.clone(), Some(address.clone()), id, true)?)
+}
 
-            smart_config.current_dca_point += 1u64;
-        } else {
-            break;
-        }
-    }
-    let (swap_amount, _origin_denom, mut messages_swap) = util::get_swap_amount_and_denom_and_message(deps.querier, smart_config.msg.pool_address.clone(), smart_config.token2_denom.clone(), smart_config.token2_amount)?;
-    
-    let mut action = String::from("sync_smart_waiting");
-    //check if the ATOM->swap rate is larger than avg_buy_price or force_finish
-    if swap_amount * util::decimal() / smart_config.token2_amount >= smart_config.target_buy_price || force_finish {
-        let (index, _max_number) =list.iter().enumerate().find(|(_i, c)| c == &&id).unwrap_or((0, &0));
-        list.remove(index);
+pub fn execute_sync_smart(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    address: Option<Addr>,
+    id: u64,
+    force_finish: bool
+) -> Result<Response, ContractError> {
+    sync_order::<SmartConfig>(deps, env, caller, address, id, force_finish)
+}
 
-        messages.append(&mut messages_swap);
+pub fn reply_swap_smart(
+    deps: DepsMut,
+    pending: PendingSwap,
+    amount: Uint128
+) -> Result<(Response, Vec<Transfer>), ContractError> {
+    let mut smart_config = smart_orders().load(deps.storage, (pending.address.clone(), pending.id))?;
+    let mut transfers: Vec<Transfer> = vec![];
+    let mut keeper_tip = Uint128::zero();
 
-        //transfer to sender
-        messages.push(util::transfer_token_message(deps.querier, smart_config.msg.token1_denom.clone(), swap_amount + smart_config.token1_amount, real_address.clone())?);
+    match pending.kind {
+        SwapKind::Start => {
+            smart_config.token2_amount = amount;
+        },
+        SwapKind::Buy | SwapKind::Sell => {
+            //pay the keeper exactly the token1 kept back from the dca amount
+            keeper_tip = smart_config.keeper_tip;
+            smart_config.token1_amount -= keeper_tip;
+            smart_config.keeper_tip = Uint128::zero();
+            smart_config.token2_amount += amount;
+            transfers.push((smart_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+        },
+        SwapKind::Increase => {
+            // token1 spent on the position so far over the token2 it holds
+            smart_config.token2_amount += amount;
+            smart_config.avg_buy_price = (smart_config.deposit_amount - smart_config.token1_amount) * util::decimal() / smart_config.token2_amount;
+            smart_config.target_buy_price = smart_config.avg_buy_price * (util::multiple() + Uint128::from(smart_config.msg.take_profit_percentage)) / util::multiple();
+            smart_config.stop_loss_price = util::get_stop_loss_price(smart_config.avg_buy_price, smart_config.msg.stop_loss_percentage)?;
+        },
+        SwapKind::Finish => {
+            //pay the keeper and transfer the rest to sender
+            keeper_tip = util::get_keeper_tip(deps.storage, &pending.keeper, &pending.address, amount)?;
+            smart_config.token1_amount = smart_config.token1_amount + amount - keeper_tip;
+            smart_config.token2_amount = Uint128::zero();
 
-        smart_config.finished = true;
-        smart_config.token1_amount += swap_amount;
-        smart_config.token2_amount = Uint128::zero();
-        action = String::from("sync_smart_success");
+            //performance fee on the realized profit
+            let (fee, fee_recipient) = util::take_performance_fee(deps.storage, &smart_config.msg.token1_denom, smart_config.deposit_amount, smart_config.token1_amount)?;
+            smart_config.token1_amount -= fee;
+            smart_config.stats.performance_fees += fee;
+            util::add_realized_pnl(&mut smart_config.stats, smart_config.deposit_amount, smart_config.token1_amount);
 
+            transfers.push((smart_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()));
+            transfers.push((smart_config.msg.token1_denom.clone(), fee, fee_recipient));
+            transfers.push((smart_config.msg.token1_denom.clone(), smart_config.token1_amount, pending.address.clone()));
+        }
+    }
+    smart_config.stats.keeper_fees += keeper_tip;
+    smart_orders().save(deps.storage, (pending.address.clone(), pending.id), &smart_config)?;
+    if smart_config.finished {
+        archive_order(deps.storage, OrderType::Smart, &pending.address, pending.id)?;
     }
-    SMART_ORDERS.save(deps.storage, (real_address.clone(), id), &smart_config)?;
 
-    return Ok(Response::new()
-        .add_attribute("action", action)
-        .add_attribute("sender", real_address.to_string())
-        .add_attribute("id", id.to_string())
-        .add_messages(messages)
-    );
+    Ok((Response::new()
+        .add_attribute("action", "reply_swap_smart")
+        .add_attribute("sender", pending.address.to_string())
+        .add_attribute("id", pending.id.to_string())
+        .add_attribute("keeper", pending.keeper.to_string())
+        .add_attribute("keeper_tip", keeper_tip), transfers))
 }
@@ -0,0 +1,213 @@
+use cosmwasm_std::{
+    DepsMut, Env, Response, Uint128, Addr
+};
+use cw20::{Balance, Denom};
+use crate::error::ContractError;
+use crate::msg::{
+    OrderType, DexKind, TwapMsg, TwapConfig, OrderStats
+};
+use crate::state::{
+    PendingSwap, twap_orders, SwapKind
+};
+
+use crate::ordergroup::{
+    archive_order, start_order, sync_order, Transfer, OrderStrategy, OrderTerms, Quote, Transition
+};
+use crate::util;
+
+pub fn execute_start_twap(
+    deps: DepsMut,
+    env: Env,
+    msg: TwapMsg,
+    balance: Balance,
+    address: Addr,
+) -> Result<Response, ContractError> {
+    start_order::<TwapConfig>(deps, env, msg, balance, address)
+}
+
+impl OrderStrategy for TwapConfig {
+    type Msg = TwapMsg;
+    const ORDER_TYPE: OrderType = OrderType::Twap;
+
+    fn terms(msg: &TwapMsg) -> OrderTerms {
+        OrderTerms {
+            token1_denom: msg.token1_denom.clone(),
+            pool_address: msg.pool_address.clone(),
+            max_slippage_bps: msg.max_slippage_bps,
+            expires_at: msg.expires_at
+        }
+    }
+
+    fn msg(&self) -> &TwapMsg {
+        &self.msg
+    }
+
+    fn token2_denom(&self) -> Denom {
+        self.token2_denom.clone()
+    }
+
+    fn dex(&self) -> DexKind {
+        self.dex
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // No swap on start, the first slice is due on the first sync
+    fn on_start(
+        msg: TwapMsg,
+        token1_amount: Uint128,
+        token2_denom: Denom,
+        dex: DexKind,
+        _quote: &dyn Quote,
+        _now: u64
+    ) -> Result<(Self, Transition), ContractError> {
+        if msg.num_slices == 0u64 || msg.total_amount < Uint128::from(msg.num_slices) {
+            return Err(ContractError::InvalidInput {});
+        }
+        if token1_amount < msg.total_amount {
+            return Err(ContractError::InsufficientAmountForTwapOrder {});
+        }
+
+        let slice_amount = msg.total_amount / Uint128::from(msg.num_slices);
+        let refund = token1_amount - msg.total_amount;
+        let twap_config = TwapConfig {
+            token2_denom,
+            dex,
+            slice_amount,
+            token1_amount: msg.total_amount,
+            keeper_tip: Uint128::zero(),
+            token2_amount: Uint128::zero(),
+            slices_filled: 0u64,
+            avg_price: Uint128::zero(),
+            last_execution: 0u64,
+            finished: false,
+            stats: OrderStats::default(),
+            msg
+        };
+        Ok((twap_config, Transition::new("")
+            .refund(refund)
+            .add_attribute("slice_amount", slice_amount)
+        ))
+    }
+
+    fn on_price(
+        &mut self,
+        quote: &dyn Quote,
+        keeper_fee_bps: u64,
+        now: u64
+    ) -> Result<Transition, ContractError> {
+        if self.slices_filled > 0u64 && now < self.last_execution + self.msg.interval {
+            return Ok(Transition::new("waiting"));
+        }
+
+        // The last slice also takes the rounding remainder
+        let mut slice_amount = self.slice_amount;
+        if self.slices_filled + 1 >= self.msg.num_slices {
+            slice_amount = self.token1_amount;
+        }
+
+        // the keeper tip is kept back from the slice, the rest is sold
+        let keeper_tip = slice_amount * Uint128::from(keeper_fee_bps) / util::bps_multiple();
+        let sell_amount = slice_amount - keeper_tip;
+
+        // min_price is token2 per token1 and a hard floor, the swap gets the same limit without any slippage
+        // the limit price rounds down so the output it asks for never falls under min_price
+        let swap_amount = quote.buy(sell_amount)?;
+        let current_price = swap_amount * util::decimal() / sell_amount;
+        let mut limit_price = None;
+        if !self.msg.min_price.is_zero() {
+            let price = util::decimal() * util::decimal() / self.msg.min_price;
+            if swap_amount < util::get_output_at_price(sell_amount, price, true) {
+                return Ok(Transition::new("price_low").add_attribute("price", current_price));
+            }
+            limit_price = Some(price);
+        }
+
+        self.token1_amount -= sell_amount;
+        self.keeper_tip = keeper_tip;
+        self.slices_filled += 1;
+        self.last_execution = now;
+
+        let mut transition = Transition::new("slice")
+            .swap(SwapKind::Buy, sell_amount)
+            .add_attribute("price", current_price);
+        if let Some(price) = limit_price {
+            transition = transition.at_limit(price);
+        }
+        Ok(transition)
+    }
+
+    // the slices not sold yet go back to the sender
+    fn on_stop(
+        &mut self,
+        _quote: &dyn Quote
+    ) -> Result<Transition, ContractError> {
+        self.finished = true;
+        Ok(Transition::new("success").refund(self.token1_amount))
+    }
+}
+
+pub fn execute_stop_twap(
+    deps: DepsMut,
+    env: Env,
+    address: Addr,
+    id: u64
+) -> Result<Response, ContractError> {
+    Ok(execute_sync_twap(deps, env, address.clone(), Some(address.clone()), id, true)?)
+}
+
+pub fn execute_sync_twap(
+    deps: DepsMut,
+    env: Env,
+    caller: Addr,
+    address: Option<Addr>,
+    id: u64,
+    force_finish: bool
+) -> Result<Response, ContractError> {
+    sync_order::<TwapConfig>(deps, env, caller, address, id, force_finish)
+}
+
+pub fn reply_swap_twap(
+    deps: DepsMut,
+    pending: PendingSwap,
+    amount: Uint128
+) -> Result<(Response, Vec<Transfer>), ContractError> {
+    let mut twap_config = twap_orders().load(deps.storage, (pending.address.clone(), pending.id))?;
+
+    //pay the keeper exactly the token1 kept back from the slice
+    let keeper_tip = twap_config.keeper_tip;
+    twap_config.token1_amount -= keeper_tip;
+    twap_config.keeper_tip = Uint128::zero();
+    twap_config.stats.keeper_fees += keeper_tip;
+
+    // the tips were never sold
+    twap_config.token2_amount += amount;
+    let sold_amount = twap_config.msg.total_amount - twap_config.token1_amount - twap_config.stats.keeper_fees;
+    if !sold_amount.is_zero() {
+        twap_config.avg_price = twap_config.token2_amount * util::decimal() / sold_amount;
+    }
+    if twap_config.slices_filled >= twap_config.msg.num_slices {
+        twap_config.finished = true;
+    }
+
+    twap_orders().save(deps.storage, (pending.address.clone(), pending.id), &twap_config)?;
+    if twap_config.finished {
+        archive_order(deps.storage, OrderType::Twap, &pending.address, pending.id)?;
+    }
+
+    // Every slice is paid out at once
+    let transfers: Vec<Transfer> = vec![
+        (twap_config.msg.token1_denom.clone(), keeper_tip, pending.keeper.clone()),
+        (twap_config.token2_denom.clone(), amount, pending.address.clone())
+    ];
+
+    Ok((Response::new()
+        .add_attribute("action", "reply_swap_twap")
+        .add_attribute("sender", pending.address.to_string())
+        .add_attribute("id", pending.id.to_string())
+        .add_attribute("keeper", pending.keeper.to_string())
+        .add_attribute("keeper_tip", keeper_tip)
+        .add_attribute("avg_price", twap_config.avg_price), transfers))
+}
@@ -1,27 +1,136 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 
-use cosmwasm_std::{Addr};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Uint128};
+use cw20::Denom;
+use cw_storage_plus::{Item, Map, IndexedMap, MultiIndex, IndexList, Index};
 
-use crate::msg::{LimitConfig, SmartConfig, GridConfig};
+use crate::msg::{LimitConfig, SmartConfig, GridConfig, DcaConfig, TwapConfig, BracketConfig, PoolConfig, OrderFill, OrderType};
+use crate::util::denom_key;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub owner: Addr,
-    pub enabled: bool
+    pub enabled: bool,
+    pub max_slippage_bps: u64,
+    pub keeper_fee_bps: u64,
+    pub performance_fee_bps: u64,
+    pub fee_recipient: Addr
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapKind {
+    Start,
+    Buy,
+    Sell,
+    Finish,
+    Increase // token1 added to an active order
+}
+
+// Swap dispatched as a submessage, settled in the reply with the amount actually received
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingSwap {
+    pub order_type: OrderType,
+    pub address: Addr,
+    pub id: u64,
+    pub keeper: Addr,
+    pub kind: SwapKind,
+    pub input_amount: Uint128,
+    pub input_denom: Denom,
+    pub output_denom: Denom,
+    pub balance_before: Uint128
+}
+
+// Secondary indexes of the active orders, each pointing back to (address, id)
+pub struct OrderIndexes<'a, T: Serialize + DeserializeOwned + Clone> {
+    pub pool: MultiIndex<'a, Addr, T, (Addr, u64)>,
+    pub finished: MultiIndex<'a, u8, T, (Addr, u64)>, // 1 once the order is finished
+    pub token1_denom: MultiIndex<'a, String, T, (Addr, u64)> // keyed by util::denom_key
+}
+
+impl<'a, T: Serialize + DeserializeOwned + Clone> IndexList<T> for OrderIndexes<'a, T> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<T>> + '_> {
+        let v: Vec<&dyn Index<T>> = vec![&self.pool, &self.finished, &self.token1_denom];
+        Box::new(v.into_iter())
+    }
+}
 
 pub const CONFIG_KEY: &str = "config";
 pub const CONFIG: Item<Config> = Item::new(CONFIG_KEY);
 
+pub const POOLS: Map<Addr, PoolConfig> = Map::new("pools");
+
+// Active ids are listed in *_ORDERS_COUNT, finished orders move to *_ORDERS_ARCHIVE once settled
 pub const LIMIT_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("limit_orders_count");
-pub const LIMIT_ORDERS: Map<(Addr, u64), LimitConfig> = Map::new("limit_orders");
+pub fn limit_orders<'a>() -> IndexedMap<'a, (Addr, u64), LimitConfig, OrderIndexes<'a, LimitConfig>> {
+    IndexedMap::new("limit_orders", OrderIndexes {
+        pool: MultiIndex::new(|order: &LimitConfig| order.msg.pool_address.clone(), "limit_orders", "limit_orders__pool"),
+        finished: MultiIndex::new(|order: &LimitConfig| order.finished as u8, "limit_orders", "limit_orders__finished"),
+        token1_denom: MultiIndex::new(|order: &LimitConfig| denom_key(&order.msg.token1_denom), "limit_orders", "limit_orders__token1_denom")
+    })
+}
+pub const LIMIT_ORDERS_ARCHIVE: Map<(Addr, u64), LimitConfig> = Map::new("limit_orders_archive");
 
 pub const SMART_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("smart_orders_count");
-pub const SMART_ORDERS: Map<(Addr, u64), SmartConfig> = Map::new("smart_orders");
+pub fn smart_orders<'a>() -> IndexedMap<'a, (Addr, u64), SmartConfig, OrderIndexes<'a, SmartConfig>> {
+    IndexedMap::new("smart_orders", OrderIndexes {
+        pool: MultiIndex::new(|order: &SmartConfig| order.msg.pool_address.clone(), "smart_orders", "smart_orders__pool"),
+        finished: MultiIndex::new(|order: &SmartConfig| order.finished as u8, "smart_orders", "smart_orders__finished"),
+        token1_denom: MultiIndex::new(|order: &SmartConfig| denom_key(&order.msg.token1_denom), "smart_orders", "smart_orders__token1_denom")
+    })
+}
+pub const SMART_ORDERS_ARCHIVE: Map<(Addr, u64), SmartConfig> = Map::new("smart_orders_archive");
 
 pub const GRID_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("grid_orders_count");
-pub const GRID_ORDERS: Map<(Addr, u64), GridConfig> = Map::new("grid_orders");
+pub fn grid_orders<'a>() -> IndexedMap<'a, (Addr, u64), GridConfig, OrderIndexes<'a, GridConfig>> {
+    IndexedMap::new("grid_orders", OrderIndexes {
+        pool: MultiIndex::new(|order: &GridConfig| order.msg.pool_address.clone(), "grid_orders", "grid_orders__pool"),
+        finished: MultiIndex::new(|order: &GridConfig| order.finished as u8, "grid_orders", "grid_orders__finished"),
+        token1_denom: MultiIndex::new(|order: &GridConfig| denom_key(&order.msg.token1_denom), "grid_orders", "grid_orders__token1_denom")
+    })
+}
+pub const GRID_ORDERS_ARCHIVE: Map<(Addr, u64), GridConfig> = Map::new("grid_orders_archive");
+
+pub const DCA_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("dca_orders_count");
+pub fn dca_orders<'a>() -> IndexedMap<'a, (Addr, u64), DcaConfig, OrderIndexes<'a, DcaConfig>> {
+    IndexedMap::new("dca_orders", OrderIndexes {
+        pool: MultiIndex::new(|order: &DcaConfig| order.msg.pool_address.clone(), "dca_orders", "dca_orders__pool"),
+        finished: MultiIndex::new(|order: &DcaConfig| order.finished as u8, "dca_orders", "dca_orders__finished"),
+        token1_denom: MultiIndex::new(|order: &DcaConfig| denom_key(&order.msg.token1_denom), "dca_orders", "dca_orders__token1_denom")
+    })
+}
+pub const DCA_ORDERS_ARCHIVE: Map<(Addr, u64), DcaConfig> = Map::new("dca_orders_archive");
+
+pub const TWAP_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("twap_orders_count");
+pub fn twap_orders<'a>() -> IndexedMap<'a, (Addr, u64), TwapConfig, OrderIndexes<'a, TwapConfig>> {
+    IndexedMap::new("twap_orders", OrderIndexes {
+        pool: MultiIndex::new(|order: &TwapConfig| order.msg.pool_address.clone(), "twap_orders", "twap_orders__pool"),
+        finished: MultiIndex::new(|order: &TwapConfig| order.finished as u8, "twap_orders", "twap_orders__finished"),
+        token1_denom: MultiIndex::new(|order: &TwapConfig| denom_key(&order.msg.token1_denom), "twap_orders", "twap_orders__token1_denom")
+    })
+}
+pub const TWAP_ORDERS_ARCHIVE: Map<(Addr, u64), TwapConfig> = Map::new("twap_orders_archive");
+
+pub const BRACKET_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("bracket_orders_count");
+pub fn bracket_orders<'a>() -> IndexedMap<'a, (Addr, u64), BracketConfig, OrderIndexes<'a, BracketConfig>> {
+    IndexedMap::new("bracket_orders", OrderIndexes {
+        pool: MultiIndex::new(|order: &BracketConfig| order.msg.pool_address.clone(), "bracket_orders", "bracket_orders__pool"),
+        finished: MultiIndex::new(|order: &BracketConfig| order.finished as u8, "bracket_orders", "bracket_orders__finished"),
+        token1_denom: MultiIndex::new(|order: &BracketConfig| denom_key(&order.msg.token1_denom), "bracket_orders", "bracket_orders__token1_denom")
+    })
+}
+pub const BRACKET_ORDERS_ARCHIVE: Map<(Addr, u64), BracketConfig> = Map::new("bracket_orders_archive");
+
+
+pub const PENDING_SWAPS: Map<u64, PendingSwap> = Map::new("pending_swaps");
+
+// Swaps made for each order, keyed by ((address, OrderType::key), id, seq)
+pub const ORDER_FILLS: Map<((Addr, u64), u64, u64), OrderFill> = Map::new("order_fills");
+
+// Amount of each denom held for active orders, keyed by util::denom_key
+pub const ESCROWED: Map<String, Uint128> = Map::new("escrowed");
 
+// Performance fees sent to the fee recipient, keyed by util::denom_key
+pub const FEES_COLLECTED: Map<String, Uint128> = Map::new("fees_collected");
@@ -1,19 +1,26 @@
 use cosmwasm_std::{
-    to_binary,  Response, StdResult, Uint128, Coin, BankMsg,
+    to_binary,  Env, Response, StdResult, Uint128, Coin, BankMsg,
     WasmMsg, WasmQuery, QueryRequest, Addr, Storage, CosmosMsg,  QuerierWrapper, BalanceResponse as NativeBalanceResponse, BankQuery
 };
 use cw20::{Balance, Cw20ExecuteMsg, Denom, BalanceResponse as CW20BalanceResponse, Cw20QueryMsg};
 use crate::error::ContractError;
-use crate::state::CONFIG;
-
-use wasmswap::msg::{ExecuteMsg as WasmswapExecuteMsg, QueryMsg as WasmswapQueryMsg, Token1ForToken2PriceResponse, Token2ForToken1PriceResponse, InfoResponse as WasmswapInfoResponse, TokenSelect};
+use crate::msg::{PoolConfig, OrderStats, DexKind};
+use crate::dex;
+use crate::state::{CONFIG, ESCROWED, FEES_COLLECTED, POOLS};
 
 pub const MAX_LIMIT: u32 = 30;
 pub const DEFAULT_LIMIT: u32 = 10;
-pub const MAX_ORDER: u64 = 10;
+pub const MAX_ORDER: u64 = 10; // active orders per address and order type, archived ones do not count
+pub const MAX_SYNC_BATCH: usize = 30;
+pub const SWAP_REPLY_ID: u64 = 1;
 
 pub fn multiple() -> Uint128 { Uint128::from(100u128) }
 pub fn decimal() -> Uint128 { Uint128::from(1000000u128) }
+pub fn bps_multiple() -> Uint128 { Uint128::from(10000u128) }
+
+pub const DEFAULT_MAX_SLIPPAGE_BPS: u64 = 100;
+pub const DEFAULT_KEEPER_FEE_BPS: u64 = 10;
+pub const DEFAULT_PERFORMANCE_FEE_BPS: u64 = 1000;
 
 pub fn check_enabled(
     storage: &mut dyn Storage,
@@ -69,28 +76,322 @@ pub fn execute_update_enabled(
     Ok(Response::new().add_attribute("action", "update_enabled"))
 }
 
+pub fn execute_update_max_slippage(
+    storage: &mut dyn Storage,
+    address: Addr,
+    max_slippage_bps: u64
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(storage, address)?;
+    check_max_slippage(max_slippage_bps)?;
+
+    CONFIG.update(storage, |mut exists| -> StdResult<_> {
+        exists.max_slippage_bps = max_slippage_bps;
+        Ok(exists)
+    })?;
+
+    Ok(Response::new().add_attribute("action", "update_max_slippage").add_attribute("max_slippage_bps", max_slippage_bps.to_string()))
+}
+
+pub fn execute_update_keeper_fee(
+    storage: &mut dyn Storage,
+    address: Addr,
+    keeper_fee_bps: u64
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(storage, address)?;
+    if Uint128::from(keeper_fee_bps) > bps_multiple() {
+        return Err(ContractError::InvalidInput {});
+    }
+
+    CONFIG.update(storage, |mut exists| -> StdResult<_> {
+        exists.keeper_fee_bps = keeper_fee_bps;
+        Ok(exists)
+    })?;
+
+    Ok(Response::new().add_attribute("action", "update_keeper_fee").add_attribute("keeper_fee_bps", keeper_fee_bps.to_string()))
+}
+
+pub fn execute_update_performance_fee(
+    storage: &mut dyn Storage,
+    address: Addr,
+    performance_fee_bps: u64,
+    fee_recipient: Addr
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(storage, address)?;
+    if Uint128::from(performance_fee_bps) > bps_multiple() {
+        return Err(ContractError::InvalidInput {});
+    }
+
+    CONFIG.update(storage, |mut exists| -> StdResult<_> {
+        exists.performance_fee_bps = performance_fee_bps;
+        exists.fee_recipient = fee_recipient.clone();
+        Ok(exists)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_performance_fee")
+        .add_attribute("performance_fee_bps", performance_fee_bps.to_string())
+        .add_attribute("fee_recipient", fee_recipient))
+}
+
+// Fee on the token1 profit of a finished order, recorded as collected
+pub fn take_performance_fee(
+    storage: &mut dyn Storage,
+    denom: &Denom,
+    principal: Uint128,
+    returned: Uint128
+) -> Result<(Uint128, Addr), ContractError> {
+    let cfg = CONFIG.load(storage)?;
+    if returned <= principal {
+        return Ok((Uint128::zero(), cfg.fee_recipient));
+    }
+    let fee = (returned - principal) * Uint128::from(cfg.performance_fee_bps) / bps_multiple();
+    FEES_COLLECTED.update(storage, denom_key(denom), |collected| -> StdResult<_> {
+        Ok(collected.unwrap_or_default() + fee)
+    })?;
+    Ok((fee, cfg.fee_recipient))
+}
+
+// Profit or loss of a finished order, from what the sender got back against the deposit
+pub fn add_realized_pnl(
+    stats: &mut OrderStats,
+    principal: Uint128,
+    returned: Uint128
+) {
+    if returned >= principal {
+        stats.realized_profit += returned - principal;
+    } else {
+        stats.realized_loss += principal - returned;
+    }
+}
+
+// Tip for syncing somebody else's order, paid from the token1 amount swapped by the sync
+pub fn get_keeper_tip(
+    storage: &dyn Storage,
+    keeper: &Addr,
+    order_owner: &Addr,
+    swapped_amount: Uint128
+) -> Result<Uint128, ContractError> {
+    if keeper == order_owner {
+        return Ok(Uint128::zero());
+    }
+    let cfg = CONFIG.load(storage)?;
+    Ok(swapped_amount * Uint128::from(cfg.keeper_fee_bps) / bps_multiple())
+}
+
+pub fn check_max_slippage(
+    max_slippage_bps: u64
+) -> Result<u64, ContractError> {
+    if Uint128::from(max_slippage_bps) > bps_multiple() {
+        return Err(ContractError::InvalidSlippage {});
+    }
+    Ok(max_slippage_bps)
+}
+
+// Slippage of the order if it is set, otherwise the contract default
+pub fn get_max_slippage(
+    storage: &dyn Storage,
+    max_slippage_bps: Option<u64>
+) -> Result<u64, ContractError> {
+    match max_slippage_bps {
+        Some(bps) => check_max_slippage(bps),
+        None => Ok(CONFIG.load(storage)?.max_slippage_bps)
+    }
+}
+
+pub fn min_output_amount(
+    amount: Uint128,
+    max_slippage_bps: u64
+) -> Uint128 {
+    amount * (bps_multiple() - Uint128::from(max_slippage_bps)) / bps_multiple()
+}
+
+// Output of a swap of input_amount at price, token1 per token2 multiplied by decimal()
+pub fn get_output_at_price(
+    input_amount: Uint128,
+    price: Uint128,
+    input_is_token1: bool
+) -> Uint128 {
+    if input_is_token1 {
+        return input_amount * decimal() / price;
+    }
+    input_amount * price / decimal()
+}
+
+pub fn execute_add_pool(
+    storage: &mut dyn Storage,
+    querier: QuerierWrapper,
+    address: Addr,
+    pool_address: Addr,
+    dex: DexKind
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(storage, address)?;
+    if POOLS.has(storage, pool_address.clone()) {
+        return Err(ContractError::PoolAlreadyRegistered {});
+    }
+
+    let (token1_denom, token2_denom) = dex::adapter(dex).pool_denoms(querier, &pool_address)?;
+
+    POOLS.save(storage, pool_address.clone(), &PoolConfig {
+        pool_address: pool_address.clone(),
+        token1_denom,
+        token2_denom,
+        dex,
+        enabled: true
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "add_pool")
+        .add_attribute("pool_address", pool_address)
+        .add_attribute("dex", dex.to_string()))
+}
+
+pub fn execute_remove_pool(
+    storage: &mut dyn Storage,
+    address: Addr,
+    pool_address: Addr
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(storage, address)?;
+    if !POOLS.has(storage, pool_address.clone()) {
+        return Err(ContractError::PoolNotRegistered {});
+    }
+    POOLS.remove(storage, pool_address.clone());
+
+    Ok(Response::new().add_attribute("action", "remove_pool").add_attribute("pool_address", pool_address))
+}
+
+pub fn execute_update_pool_enabled(
+    storage: &mut dyn Storage,
+    address: Addr,
+    pool_address: Addr,
+    enabled: bool
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(storage, address)?;
+
+    POOLS.update(storage, pool_address.clone(), |exists| -> Result<_, ContractError> {
+        let mut pool = exists.ok_or(ContractError::PoolNotRegistered {})?;
+        pool.enabled = enabled;
+        Ok(pool)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_pool_enabled")
+        .add_attribute("pool_address", pool_address)
+        .add_attribute("enabled", enabled.to_string()))
+}
+
+// New orders can only be started on registered and enabled pools
+pub fn check_pool_registered(
+    storage: &dyn Storage,
+    pool_address: Addr
+) -> Result<PoolConfig, ContractError> {
+    let pool = POOLS.may_load(storage, pool_address)?.ok_or(ContractError::PoolNotRegistered {})?;
+    if !pool.enabled {
+        return Err(ContractError::PoolDisabled {});
+    }
+    Ok(pool)
+}
+
+// The adapter of the pool, once the token is checked to be one of its two denoms
 pub fn check_token_and_pool(
+    storage: &dyn Storage,
     querier: QuerierWrapper,
     denom: Denom,
     pool_address: Addr,
-) -> Result<bool, ContractError> {
-    let pool_info_response: WasmswapInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: pool_address.clone().into(),
-        msg: to_binary(&WasmswapQueryMsg::Info {})?,
-    }))?;
+) -> Result<DexKind, ContractError> {
+    let pool = check_pool_registered(storage, pool_address.clone())?;
+
+    let (token1_denom, token2_denom) = dex::adapter(pool.dex).pool_denoms(querier, &pool_address)?;
 
-    if denom != pool_info_response.token1_denom && denom != pool_info_response.token2_denom {
+    if denom != token1_denom && denom != token2_denom {
         return Err(ContractError::PoolAndTokenMismatch{});
     }
+    Ok(pool.dex)
+}
+
+pub fn check_expires_at(
+    env: &Env,
+    expires_at: Option<u64>
+) -> Result<(), ContractError> {
+    if let Some(expires_at) = expires_at {
+        if expires_at <= env.block.time.seconds() {
+            return Err(ContractError::InvalidExpiration {});
+        }
+    }
+    Ok(())
+}
 
-    if denom == pool_info_response.token1_denom {
-        return Ok(true);
+pub fn is_expired(
+    env: &Env,
+    expires_at: Option<u64>
+) -> bool {
+    match expires_at {
+        Some(expires_at) => env.block.time.seconds() >= expires_at,
+        None => false
+    }
+}
+
+pub fn get_stop_loss_price(
+    avg_buy_price: Uint128,
+    stop_loss_percentage: Option<u64>
+) -> Result<Option<Uint128>, ContractError> {
+    match stop_loss_percentage {
+        Some(percentage) => {
+            if Uint128::from(percentage) >= multiple() {
+                return Err(ContractError::InvalidInput {});
+            }
+            Ok(Some(avg_buy_price * (multiple() - Uint128::from(percentage)) / multiple()))
+        },
+        None => Ok(None)
     }
+}
 
-    if denom == pool_info_response.token2_denom {
-        return Ok(false);
+pub fn denom_key(
+    denom: &Denom
+) -> String {
+    match denom {
+        Denom::Native(native_str) => format!("native:{}", native_str),
+        Denom::Cw20(cw20_address) => format!("cw20:{}", cw20_address)
     }
-    return Err(ContractError::PoolAndTokenMismatch{});
+}
+
+pub fn get_escrowed(
+    storage: &dyn Storage,
+    denom: &Denom
+) -> StdResult<Uint128> {
+    Ok(ESCROWED.may_load(storage, denom_key(denom))?.unwrap_or_default())
+}
+
+pub fn get_fees_collected(
+    storage: &dyn Storage,
+    denom: &Denom
+) -> StdResult<Uint128> {
+    Ok(FEES_COLLECTED.may_load(storage, denom_key(denom))?.unwrap_or_default())
+}
+
+pub fn increase_escrow(
+    storage: &mut dyn Storage,
+    denom: &Denom,
+    amount: Uint128
+) -> StdResult<Uint128> {
+    ESCROWED.update(storage, denom_key(denom), |escrowed| -> StdResult<_> {
+        Ok(escrowed.unwrap_or_default() + amount)
+    })
+}
+
+pub fn decrease_escrow(
+    storage: &mut dyn Storage,
+    denom: &Denom,
+    amount: Uint128
+) -> StdResult<Uint128> {
+    ESCROWED.update(storage, denom_key(denom), |escrowed| -> StdResult<_> {
+        Ok(escrowed.unwrap_or_default().checked_sub(amount)?)
+    })
 }
 
 pub fn get_amount_of_denom(
@@ -140,107 +441,38 @@ pub fn get_amount_of_denom(
 
 pub fn get_swap_amount_and_denom_and_message(
     querier: QuerierWrapper,
+    dex: DexKind,
     pool_address: Addr,
     denom: Denom,
     amount: Uint128,
+    limit_output: Option<Uint128>,
+    max_slippage_bps: u64,
 ) -> Result<(Uint128, Denom, Vec<CosmosMsg>), ContractError> {
+    let adapter = dex::adapter(dex);
+    let (token1_denom, token2_denom) = adapter.pool_denoms(querier, &pool_address)?;
 
-    let pool_info_response: WasmswapInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: pool_address.clone().into(),
-        msg: to_binary(&WasmswapQueryMsg::Info {})?,
-    }))?;
-
-    if denom != pool_info_response.token1_denom && denom != pool_info_response.token2_denom {
+    if denom != token1_denom && denom != token2_denom {
         return Err(ContractError::PoolAndTokenMismatch{});
     }
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let swap_amount;
-    let other_denom: Denom;
-    if denom == pool_info_response.token1_denom {
-        let token2_price_response: Token1ForToken2PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-            contract_addr: pool_address.clone().into(),
-            msg: to_binary(&WasmswapQueryMsg::Token1ForToken2Price {
-                token1_amount: amount
-            })?,
-        }))?;
-
-        other_denom = pool_info_response.token2_denom;
-        swap_amount = token2_price_response.token2_amount;
-        let messages_swap = swap_token_messages(denom, TokenSelect::Token1, amount, swap_amount, pool_address.clone())?;
-        for i in 0..messages_swap.len() {
-            messages.push(messages_swap[i].clone());
-        }
-
-    } else {
-        let token1_price_response: Token2ForToken1PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-            contract_addr: pool_address.clone().into(),
-            msg: to_binary(&WasmswapQueryMsg::Token2ForToken1Price {
-                token2_amount: amount
-            })?,
-        }))?;
-
-        other_denom = pool_info_response.token1_denom;
-        swap_amount = token1_price_response.token1_amount;
-
-        let messages_swap = swap_token_messages(denom, TokenSelect::Token2, amount, swap_amount, pool_address.clone())?;
-        for i in 0..messages_swap.len() {
-            messages.push(messages_swap[i].clone());
-        }
+    let input_is_token1 = denom == token1_denom;
+    let mut other_denom = token1_denom;
+    if input_is_token1 {
+        other_denom = token2_denom;
     }
-    Ok((swap_amount, other_denom, messages))
-}
 
+    let swap_amount = adapter.quote(querier, &pool_address, &denom, input_is_token1, amount)?;
 
-pub fn swap_token_messages(
-    denom: Denom,
-    input_token: TokenSelect,
-    input_amount: Uint128,
-    min_output: Uint128,
-    pool_address: Addr
-) -> Result<Vec<CosmosMsg>, ContractError> {
-
-    let mut messages: Vec<CosmosMsg> = vec![];
-    match denom.clone() {
-        Denom::Native(native_str) => {
-            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: pool_address.clone().into(),
-                funds: vec![Coin {
-                    denom: native_str,
-                    amount: input_amount
-                }],
-                msg: to_binary(&WasmswapExecuteMsg::Swap {
-                    input_token,
-                    input_amount,
-                    min_output,
-                    expiration: None
-                })?,
-            }));
-
-        },
-        Denom::Cw20(cw20_address) => {
-            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: cw20_address.clone().into(),
-                funds: vec![],
-                msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
-                    spender: pool_address.clone().into(),
-                    amount: input_amount,
-                    expires: None
-                })?,
-            }));
-            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: pool_address.clone().into(),
-                funds: vec![],
-                msg: to_binary(&WasmswapExecuteMsg::Swap {
-                    input_token,
-                    input_amount,
-                    min_output,
-                    expiration: None
-                })?,
-            }));
+    // Every swap is bounded by the live quote less the slippage, a limit set by the user can only raise that bound
+    let mut min_output = min_output_amount(swap_amount, max_slippage_bps);
+    if let Some(limit_output) = limit_output {
+        if swap_amount < limit_output {
+            return Err(ContractError::SlippageExceeded {});
         }
+        min_output = min_output.max(limit_output);
     }
-    return Ok(messages);
+    let messages = adapter.swap_messages(querier, &pool_address, &denom, input_is_token1, amount, min_output)?;
+    Ok((swap_amount, other_denom, messages))
 }
 
 
@@ -0,0 +1,722 @@
+use cosmwasm_std::{
+    coin, coins, from_json, to_json_binary, Addr, BankMsg, Binary, ContractResult, CosmosMsg, Decimal, Deps, DepsMut, Empty, Env, MemoryStorage, MessageInfo, OwnedDeps, QuerierWrapper, Response, StdError, StdResult, SystemResult, Uint128, WasmMsg, WasmQuery
+};
+use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier};
+use cw2::set_contract_version;
+use cw20::Denom;
+use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
+
+use universe_orders::contract;
+use universe_orders::dex::{self, PairExecuteMsg, SimulationResponse};
+use universe_orders::ContractError;
+use universe_orders::msg::{
+    DexKind, EscrowResponse, ExecuteMsg, InstantiateMsg, LimitConfig, LimitMsg, LimitPhase, MigrateMsg, GridLevelState, OrderConfig, OrderForAddressIdsResponse, OrderListResponse, OrderResponse, OrderStats, OrderType, QueryMsg, TwapConfig, TwapMsg
+};
+use universe_orders::state::{
+    Config, CONFIG, ESCROWED, LIMIT_ORDERS_COUNT, TWAP_ORDERS_COUNT, limit_orders, twap_orders
+};
+use universe_orders::util;
+
+const TOKEN1: &str = "ujuno";
+const TOKEN2: &str = "uatom";
+const OWNER: &str = "owner";
+const TREASURY: &str = "treasury";
+const KEEPER: &str = "keeper";
+
+// Constant product pool answering the wasmswap messages the orders use, its reserves are its bank balances
+mod mock_pool {
+    use super::*;
+    use wasmswap::msg::{
+        ExecuteMsg, QueryMsg, InfoResponse, Token1ForToken2PriceResponse, Token2ForToken1PriceResponse, TokenSelect
+    };
+
+    fn reserves(deps: Deps, env: &Env) -> StdResult<(Uint128, Uint128)> {
+        Ok((
+            deps.querier.query_balance(&env.contract.address, TOKEN1)?.amount,
+            deps.querier.query_balance(&env.contract.address, TOKEN2)?.amount
+        ))
+    }
+
+    // output with the 0.3% pool fee taken from the input
+    fn output(input_amount: Uint128, input_reserve: Uint128, output_reserve: Uint128) -> Uint128 {
+        let input_with_fee = input_amount * Uint128::from(997u128);
+        input_with_fee * output_reserve / (input_reserve * Uint128::from(1000u128) + input_with_fee)
+    }
+
+    pub fn instantiate(_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+
+    pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+        match msg {
+            ExecuteMsg::Swap { input_token, input_amount, min_output, .. } => {
+                // the input is already part of the balance
+                let (token1_reserve, token2_reserve) = reserves(deps.as_ref(), &env)?;
+                let (output_amount, output_denom) = match input_token {
+                    TokenSelect::Token1 => (output(input_amount, token1_reserve - input_amount, token2_reserve), TOKEN2),
+                    TokenSelect::Token2 => (output(input_amount, token2_reserve - input_amount, token1_reserve), TOKEN1)
+                };
+                if output_amount < min_output {
+                    return Err(StdError::generic_err("Swap output below min_output"));
+                }
+                Ok(Response::new().add_message(BankMsg::Send {
+                    to_address: info.sender.to_string(),
+                    amount: coins(output_amount.u128(), output_denom)
+                }))
+            },
+            _ => Err(StdError::generic_err("Not supported by the mock pool"))
+        }
+    }
+
+    pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+        let (token1_reserve, token2_reserve) = reserves(deps, &env)?;
+        match msg {
+            QueryMsg::Info {} => to_json_binary(&InfoResponse {
+                token1_reserve,
+                token1_denom: Denom::Native(TOKEN1.to_string()),
+                token2_reserve,
+                token2_denom: Denom::Native(TOKEN2.to_string()),
+                lp_token_supply: Uint128::zero(),
+                lp_token_address: String::new()
+            }),
+            QueryMsg::Token1ForToken2Price { token1_amount } => to_json_binary(&Token1ForToken2PriceResponse {
+                token2_amount: output(token1_amount, token1_reserve, token2_reserve)
+            }),
+            QueryMsg::Token2ForToken1Price { token2_amount } => to_json_binary(&Token2ForToken1PriceResponse {
+                token1_amount: output(token2_amount, token2_reserve, token1_reserve)
+            }),
+            _ => Err(StdError::generic_err("Not supported by the mock pool"))
+        }
+    }
+}
+
+fn orders_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(contract::execute, contract::instantiate, contract::query)
+        .with_reply(contract::reply)
+        .with_migrate(contract::migrate))
+}
+
+fn pool_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(mock_pool::execute, mock_pool::instantiate, mock_pool::query))
+}
+
+struct Suite {
+    app: App,
+    orders: Addr,
+    pool: Addr
+}
+
+impl Suite {
+    // A registered pool of 1000 token1 for 1000 token2, every user holds 100 token1
+    fn new(users: &[&str]) -> Self {
+        let users: Vec<String> = users.iter().map(|user| user.to_string()).collect();
+        let mut app = AppBuilder::new().build(|router, _api, storage| {
+            for user in users.iter() {
+                router.bank.init_balance(storage, &Addr::unchecked(user), coins(100_000_000, TOKEN1)).unwrap();
+            }
+        });
+
+        let orders_code = app.store_code(orders_contract());
+        let pool_code = app.store_code(pool_contract());
+        let orders = app.instantiate_contract(orders_code, Addr::unchecked(OWNER), &InstantiateMsg {
+            treasury_address: Addr::unchecked(TREASURY)
+        }, &[], "orders", Some(OWNER.to_string())).unwrap();
+        let pool = app.instantiate_contract(pool_code, Addr::unchecked(OWNER), &Empty {}, &[], "pool", None).unwrap();
+
+        let mut suite = Suite { app, orders, pool };
+        suite.set_reserves(1_000_000_000, 1_000_000_000);
+        suite.app.execute_contract(Addr::unchecked(OWNER), suite.orders.clone(), &ExecuteMsg::AddPool {
+            pool_address: suite.pool.clone(),
+            dex: None
+        }, &[]).unwrap();
+        suite
+    }
+
+    fn set_reserves(&mut self, token1_reserve: u128, token2_reserve: u128) {
+        let pool = self.pool.clone();
+        self.app.init_modules(|router, _api, storage| {
+            router.bank.init_balance(storage, &pool, vec![coin(token1_reserve, TOKEN1), coin(token2_reserve, TOKEN2)]).unwrap();
+        });
+    }
+
+    fn start_limit(&mut self, user: &str, amount: u128, entry_price: Option<Uint128>) {
+        self.app.execute_contract(Addr::unchecked(user), self.orders.clone(), &ExecuteMsg::StartLimit(LimitMsg {
+            token1_denom: Denom::Native(TOKEN1.to_string()),
+            pool_address: self.pool.clone(),
+            take_profit_percentage: 5u64,
+            stop_loss_percentage: None,
+            trail_percentage: None,
+            entry_price,
+            max_slippage_bps: None,
+            expires_at: None
+        }), &coins(amount, TOKEN1)).unwrap();
+    }
+
+    fn limit_order(&self, user: &str, id: u64) -> LimitConfig {
+        let response: OrderResponse = self.app.wrap().query_wasm_smart(self.orders.clone(), &QueryMsg::Order {
+            order_type: OrderType::Limit,
+            address: Addr::unchecked(user),
+            id
+        }).unwrap();
+        match response.order {
+            OrderConfig::Limit(order) => order,
+            order => panic!("not a limit order: {:?}", order)
+        }
+    }
+
+    fn balance(&self, address: &Addr, denom: &str) -> Uint128 {
+        self.app.wrap().query_balance(address, denom).unwrap().amount
+    }
+
+    // The escrow the contract keeps must be exactly what it holds
+    fn assert_escrow_matches_balance(&self, denom: &str) -> Uint128 {
+        let escrow: EscrowResponse = self.app.wrap().query_wasm_smart(self.orders.clone(), &QueryMsg::Escrow {
+            denom: Denom::Native(denom.to_string())
+        }).unwrap();
+        assert_eq!(escrow.escrowed, self.balance(&self.orders, denom), "{} escrow", denom);
+        assert!(escrow.surplus.is_zero());
+        escrow.escrowed
+    }
+}
+
+#[test]
+fn sync_settles_the_measured_output() {
+    let mut suite = Suite::new(&["alice"]);
+    suite.start_limit("alice", 1_000_000, None);
+
+    // the start swap output is what the order holds
+    let order = suite.limit_order("alice", 0);
+    assert!(!order.token2_amount.is_zero());
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN2), order.token2_amount);
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN1), Uint128::zero());
+
+    // token2 goes up far enough for the take profit
+    suite.set_reserves(1_200_000_000, 850_000_000);
+    let pool_before = suite.balance(&suite.pool, TOKEN1);
+    suite.app.execute_contract(Addr::unchecked(KEEPER), suite.orders.clone(), &ExecuteMsg::Sync {
+        order_type: OrderType::Limit,
+        address: Some(Addr::unchecked("alice")),
+        id: 0
+    }, &[]).unwrap();
+    let swap_output = pool_before - suite.balance(&suite.pool, TOKEN1);
+
+    let order = suite.limit_order("alice", 0);
+    assert!(order.finished);
+    assert!(order.token2_amount.is_zero());
+    assert!(!order.stats.keeper_fees.is_zero());
+    assert_eq!(order.token1_amount + order.stats.keeper_fees + order.stats.performance_fees, swap_output);
+    assert_eq!(suite.balance(&Addr::unchecked("alice"), TOKEN1), Uint128::from(99_000_000u128) + order.token1_amount);
+    assert_eq!(suite.balance(&Addr::unchecked(KEEPER), TOKEN1), order.stats.keeper_fees);
+    assert_eq!(suite.balance(&Addr::unchecked(TREASURY), TOKEN1), order.stats.performance_fees);
+
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN1), Uint128::zero());
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN2), Uint128::zero());
+}
+
+#[test]
+fn sync_batch_settles_two_swaps_into_the_same_denom() {
+    let mut suite = Suite::new(&["alice", "bob", "carol"]);
+    suite.start_limit("alice", 1_000_000, None);
+    suite.start_limit("bob", 2_000_000, None);
+    // waits far below the price, its token1 stays in the contract next to the swap outputs
+    suite.start_limit("carol", 5_000_000, Some(Uint128::from(500_000u128)));
+
+    let held = suite.limit_order("alice", 0).token2_amount + suite.limit_order("bob", 0).token2_amount;
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN2), held);
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN1), Uint128::from(5_000_000u128));
+
+    // both take profits sell into token1 within the same batch
+    suite.set_reserves(1_200_000_000, 850_000_000);
+    let pool_before = suite.balance(&suite.pool, TOKEN1);
+    suite.app.execute_contract(Addr::unchecked(KEEPER), suite.orders.clone(), &ExecuteMsg::SyncBatch {
+        orders: vec![
+            (OrderType::Limit, Addr::unchecked("alice"), 0),
+            (OrderType::Limit, Addr::unchecked("bob"), 0),
+            (OrderType::Limit, Addr::unchecked("carol"), 0)
+        ]
+    }, &[]).unwrap();
+    let swap_output = pool_before - suite.balance(&suite.pool, TOKEN1);
+
+    let alice = suite.limit_order("alice", 0);
+    let bob = suite.limit_order("bob", 0);
+    assert!(alice.finished && bob.finished);
+    // each reply measured its own swap, together they add up to what the pool paid
+    assert!(bob.token1_amount > alice.token1_amount);
+    let settled = alice.token1_amount + alice.stats.keeper_fees + alice.stats.performance_fees
+        + bob.token1_amount + bob.stats.keeper_fees + bob.stats.performance_fees;
+    assert_eq!(settled, swap_output);
+    assert_eq!(suite.balance(&Addr::unchecked("alice"), TOKEN1), Uint128::from(99_000_000u128) + alice.token1_amount);
+    assert_eq!(suite.balance(&Addr::unchecked("bob"), TOKEN1), Uint128::from(98_000_000u128) + bob.token1_amount);
+    assert_eq!(suite.balance(&Addr::unchecked(KEEPER), TOKEN1), alice.stats.keeper_fees + bob.stats.keeper_fees);
+
+    let carol = suite.limit_order("carol", 0);
+    assert!(!carol.finished);
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN1), carol.token1_amount);
+    assert_eq!(suite.assert_escrow_matches_balance(TOKEN2), Uint128::zero());
+}
+
+#[test]
+fn increase_is_refused_once_the_pool_is_disabled() {
+    let mut suite = Suite::new(&["alice"]);
+    suite.start_limit("alice", 1_000_000, None);
+    suite.app.execute_contract(Addr::unchecked(OWNER), suite.orders.clone(), &ExecuteMsg::UpdatePoolEnabled {
+        pool_address: suite.pool.clone(),
+        enabled: false
+    }, &[]).unwrap();
+
+    let err = suite.app.execute_contract(Addr::unchecked("alice"), suite.orders.clone(), &ExecuteMsg::IncreaseOrder {
+        order_type: OrderType::Limit,
+        id: 0
+    }, &coins(1_000_000, TOKEN1)).unwrap_err();
+    assert_eq!(err.root_cause().to_string(), ContractError::PoolDisabled {}.to_string());
+    assert_eq!(suite.limit_order("alice", 0).initial_token1_amount, Uint128::from(1_000_000u128));
+}
+
+#[test]
+fn migrate_rebuilds_the_escrow_from_active_orders() {
+    let mut deps = mock_dependencies();
+    set_contract_version(deps.as_mut().storage, "universe_orders", "1.0.0").unwrap();
+    CONFIG.save(deps.as_mut().storage, &Config {
+        owner: Addr::unchecked(OWNER),
+        enabled: true,
+        max_slippage_bps: util::DEFAULT_MAX_SLIPPAGE_BPS,
+        keeper_fee_bps: util::DEFAULT_KEEPER_FEE_BPS,
+        performance_fee_bps: util::DEFAULT_PERFORMANCE_FEE_BPS,
+        fee_recipient: Addr::unchecked(TREASURY)
+    }).unwrap();
+
+    let token1_denom = Denom::Native(TOKEN1.to_string());
+    let token2_denom = Denom::Native(TOKEN2.to_string());
+    let alice = Addr::unchecked("alice");
+    limit_orders().save(deps.as_mut().storage, (alice.clone(), 0), &LimitConfig {
+        msg: LimitMsg {
+            token1_denom: token1_denom.clone(),
+            pool_address: Addr::unchecked("pool"),
+            take_profit_percentage: 5u64,
+            stop_loss_percentage: None,
+            trail_percentage: None,
+            entry_price: None,
+            max_slippage_bps: None,
+            expires_at: None
+        },
+        phase: LimitPhase::TakeProfit,
+        token2_denom: token2_denom.clone(),
+        dex: DexKind::Wasmswap,
+        initial_token1_amount: Uint128::from(1_000_000u128),
+        token1_amount: Uint128::zero(),
+        token2_amount: Uint128::from(990_000u128),
+        avg_buy_price: Uint128::from(1_010_101u128),
+        target_buy_price: Uint128::from(1_060_606u128),
+        stop_loss_price: None,
+        peak_price: None,
+        trail_trigger_price: None,
+        finished: false,
+        stats: OrderStats::default()
+    }).unwrap();
+    LIMIT_ORDERS_COUNT.save(deps.as_mut().storage, alice.clone(), &(vec![0], 1)).unwrap();
+
+    // the slices sold so far were paid out, only the token1 left is held
+    twap_orders().save(deps.as_mut().storage, (alice.clone(), 0), &TwapConfig {
+        msg: TwapMsg {
+            token1_denom: token1_denom.clone(),
+            pool_address: Addr::unchecked("pool"),
+            total_amount: Uint128::from(3_000_000u128),
+            num_slices: 3u64,
+            interval: 60u64,
+            min_price: Uint128::zero(),
+            max_slippage_bps: None,
+            expires_at: None
+        },
+        token2_denom: token2_denom.clone(),
+        dex: DexKind::Wasmswap,
+        slice_amount: Uint128::from(1_000_000u128),
+        token1_amount: Uint128::from(2_000_000u128),
+        keeper_tip: Uint128::zero(),
+        token2_amount: Uint128::from(995_000u128),
+        slices_filled: 1u64,
+        avg_price: Uint128::from(995_000u128),
+        last_execution: 0u64,
+        finished: false,
+        stats: OrderStats::default()
+    }).unwrap();
+    TWAP_ORDERS_COUNT.save(deps.as_mut().storage, alice, &(vec![0], 1)).unwrap();
+
+    // nothing recorded for token2 and a stale amount for token1
+    ESCROWED.save(deps.as_mut().storage, util::denom_key(&token1_denom), &Uint128::from(7u128)).unwrap();
+
+    contract::migrate(deps.as_mut(), mock_env(), MigrateMsg { fee_recipient: None }).unwrap();
+
+    assert_eq!(util::get_escrowed(deps.as_ref().storage, &token1_denom).unwrap(), Uint128::from(2_000_000u128));
+    assert_eq!(util::get_escrowed(deps.as_ref().storage, &token2_denom).unwrap(), Uint128::from(990_000u128));
+
+    // the TWAP order is indexed like the others
+    let response: OrderListResponse = from_json(contract::query(deps.as_ref(), mock_env(), QueryMsg::OrdersByPool {
+        order_type: OrderType::Twap,
+        pool_address: Addr::unchecked("pool"),
+        start_after: None,
+        limit: None
+    }).unwrap()).unwrap();
+    assert_eq!(response.orders.len(), 1);
+}
+
+// The records of the first release, written the way it stored them
+mod baseline {
+    use cosmwasm_std::{Addr, Uint128};
+    use cw20::Denom;
+    use cw_storage_plus::{Item, Map};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Config {
+        pub owner: Addr,
+        pub enabled: bool
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct LimitMsg {
+        pub token1_denom: Denom,
+        pub pool_address: Addr,
+        pub take_profit_percentage: u64
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct LimitConfig {
+        pub msg: LimitMsg,
+        pub token2_denom: Denom,
+        pub initial_token1_amount: Uint128,
+        pub token1_amount: Uint128,
+        pub token2_amount: Uint128,
+        pub avg_buy_price: Uint128,
+        pub target_buy_price: Uint128,
+        pub finished: bool
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SmartMsg {
+        pub token1_denom: Denom,
+        pub pool_address: Addr,
+        pub take_profit_percentage: u64,
+        pub initial_token1_amount: Uint128,
+        pub num_dca_orders: u64,
+        pub dca_step: u64,
+        pub dca_step_multiplier: u64,
+        pub dca_order_size: Uint128,
+        pub dca_order_size_multiplier: u64
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SmartConfig {
+        pub msg: SmartMsg,
+        pub token2_denom: Denom,
+        pub token1_amount: Uint128,
+        pub token2_amount: Uint128,
+        pub avg_buy_price: Uint128,
+        pub target_buy_price: Uint128,
+        pub finished: bool,
+        pub dca_prices: Vec<Uint128>,
+        pub dca_amounts: Vec<Uint128>,
+        pub current_dca_point: u64
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct GridMsg {
+        pub token1_denom: Denom,
+        pub pool_address: Addr,
+        pub total_amount: Uint128,
+        pub num_grid_pairs: u64,
+        pub price_range_percentage: u64
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct GridConfig {
+        pub msg: GridMsg,
+        pub token2_denom: Denom,
+        pub buy_prices: Vec<Uint128>,
+        pub sell_prices: Vec<Uint128>,
+        pub order_amount: Uint128,
+        pub finished: bool,
+        pub buy_step: u64,
+        pub sell_step: u64,
+        pub token1_amount: Uint128,
+        pub token2_amount: Uint128
+    }
+
+    pub const CONFIG: Item<Config> = Item::new("config");
+    pub const LIMIT_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("limit_orders_count");
+    pub const LIMIT_ORDERS: Map<(Addr, u64), LimitConfig> = Map::new("limit_orders");
+    pub const SMART_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("smart_orders_count");
+    pub const SMART_ORDERS: Map<(Addr, u64), SmartConfig> = Map::new("smart_orders");
+    pub const GRID_ORDERS_COUNT: Map<Addr, (Vec<u64>, u64)> = Map::new("grid_orders_count");
+    pub const GRID_ORDERS: Map<(Addr, u64), GridConfig> = Map::new("grid_orders");
+}
+
+fn amounts(values: &[u128]) -> Vec<Uint128> {
+    values.iter().map(|value| Uint128::from(*value)).collect()
+}
+
+#[test]
+fn migrate_converts_a_baseline_store() {
+    let mut deps = mock_dependencies();
+    let storage = deps.as_mut().storage;
+    set_contract_version(storage, "universe_orders", "1.0.0").unwrap();
+    baseline::CONFIG.save(storage, &baseline::Config { owner: Addr::unchecked(OWNER), enabled: true }).unwrap();
+
+    let token1_denom = Denom::Native(TOKEN1.to_string());
+    let token2_denom = Denom::Native(TOKEN2.to_string());
+    let pool = Addr::unchecked("pool");
+    let (alice, bob, carol, dave) = (Addr::unchecked("alice"), Addr::unchecked("bob"), Addr::unchecked("carol"), Addr::unchecked("dave"));
+
+    let limit = |finished: bool| baseline::LimitConfig {
+        msg: baseline::LimitMsg { token1_denom: token1_denom.clone(), pool_address: pool.clone(), take_profit_percentage: 5u64 },
+        token2_denom: token2_denom.clone(),
+        initial_token1_amount: Uint128::from(1_000_000u128),
+        token1_amount: Uint128::zero(),
+        token2_amount: Uint128::from(990_000u128),
+        avg_buy_price: Uint128::from(1_010_101u128),
+        target_buy_price: Uint128::from(1_060_606u128),
+        finished
+    };
+    baseline::LIMIT_ORDERS.save(storage, (alice.clone(), 0), &limit(false)).unwrap();
+    baseline::LIMIT_ORDERS_COUNT.save(storage, alice.clone(), &(vec![0], 1)).unwrap();
+    // stopped orders stayed in the id list
+    baseline::LIMIT_ORDERS.save(storage, (dave.clone(), 0), &limit(true)).unwrap();
+    baseline::LIMIT_ORDERS_COUNT.save(storage, dave.clone(), &(vec![0], 1)).unwrap();
+
+    // 8 sent for a ladder of 1 + 2 + 4, the first dca step bought, the refund of 1 still counted
+    baseline::SMART_ORDERS.save(storage, (bob.clone(), 0), &baseline::SmartConfig {
+        msg: baseline::SmartMsg {
+            token1_denom: token1_denom.clone(),
+            pool_address: pool.clone(),
+            take_profit_percentage: 5u64,
+            initial_token1_amount: Uint128::from(1_000_000u128),
+            num_dca_orders: 2u64,
+            dca_step: 5u64,
+            dca_step_multiplier: 1u64,
+            dca_order_size: Uint128::from(1_000_000u128),
+            dca_order_size_multiplier: 2u64
+        },
+        token2_denom: token2_denom.clone(),
+        token1_amount: Uint128::from(5_000_000u128),
+        token2_amount: Uint128::from(2_900_000u128),
+        avg_buy_price: Uint128::from(8_080_808u128),
+        target_buy_price: Uint128::from(8_484_848u128),
+        finished: false,
+        dca_prices: amounts(&[7_676_767, 7_272_727]),
+        dca_amounts: amounts(&[2_000_000, 4_000_000]),
+        current_dca_point: 1u64
+    }).unwrap();
+    baseline::SMART_ORDERS_COUNT.save(storage, bob.clone(), &(vec![0], 1)).unwrap();
+
+    // the first buy level filled
+    baseline::GRID_ORDERS.save(storage, (carol.clone(), 0), &baseline::GridConfig {
+        msg: baseline::GridMsg {
+            token1_denom: token1_denom.clone(),
+            pool_address: pool.clone(),
+            total_amount: Uint128::from(4_000_000u128),
+            num_grid_pairs: 2u64,
+            price_range_percentage: 10u64
+        },
+        token2_denom: token2_denom.clone(),
+        buy_prices: amounts(&[950_000, 900_000]),
+        sell_prices: amounts(&[1_050_000, 1_100_000]),
+        order_amount: Uint128::from(1_000_000u128),
+        finished: false,
+        buy_step: 1u64,
+        sell_step: 0u64,
+        token1_amount: Uint128::from(1_000_000u128),
+        token2_amount: Uint128::from(3_050_000u128)
+    }).unwrap();
+    baseline::GRID_ORDERS_COUNT.save(storage, carol.clone(), &(vec![0], 1)).unwrap();
+
+    // the baseline config has no fee recipient to keep
+    let err = contract::migrate(deps.as_mut(), mock_env(), MigrateMsg { fee_recipient: None }).unwrap_err();
+    assert_eq!(err, ContractError::MissingFeeRecipient {});
+
+    contract::migrate(deps.as_mut(), mock_env(), MigrateMsg { fee_recipient: Some(Addr::unchecked(TREASURY)) }).unwrap();
+
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.owner, Addr::unchecked(OWNER));
+    assert_eq!(config.fee_recipient, Addr::unchecked(TREASURY));
+    assert_eq!(config.keeper_fee_bps, 0u64);
+    assert_eq!(config.performance_fee_bps, 0u64);
+
+    let order = |order_type: OrderType, address: &Addr| -> OrderConfig {
+        let response: OrderResponse = from_json(contract::query(deps.as_ref(), mock_env(), QueryMsg::Order {
+            order_type,
+            address: address.clone(),
+            id: 0
+        }).unwrap()).unwrap();
+        response.order
+    };
+    match order(OrderType::Limit, &alice) {
+        OrderConfig::Limit(limit) => {
+            assert_eq!(limit.phase, LimitPhase::TakeProfit);
+            assert_eq!(limit.token2_amount, Uint128::from(990_000u128));
+            assert!(!limit.finished);
+        },
+        order => panic!("not a limit order: {:?}", order)
+    }
+    match order(OrderType::Smart, &bob) {
+        OrderConfig::Smart(smart) => {
+            assert_eq!(smart.deposit_amount, Uint128::from(7_000_000u128));
+            assert_eq!(smart.token1_amount, Uint128::from(4_000_000u128));
+            assert_eq!(smart.current_dca_point, 1u64);
+        },
+        order => panic!("not a smart order: {:?}", order)
+    }
+    match order(OrderType::Grid, &carol) {
+        OrderConfig::Grid(grid) => {
+            let levels: Vec<(Uint128, GridLevelState)> = grid.levels.into_iter().map(|level| (level.price, level.state)).collect();
+            assert_eq!(levels, vec![
+                (Uint128::from(900_000u128), GridLevelState::Buy),
+                (Uint128::from(950_000u128), GridLevelState::Idle),
+                (Uint128::from(1_000_000u128), GridLevelState::Idle),
+                (Uint128::from(1_050_000u128), GridLevelState::Sell),
+                (Uint128::from(1_100_000u128), GridLevelState::Sell)
+            ]);
+            assert_eq!(grid.msg.price_range_percentage, Some(10u64));
+        },
+        order => panic!("not a grid order: {:?}", order)
+    }
+
+    // the finished order is archived, the active ones are indexed and escrowed
+    let ids: OrderForAddressIdsResponse = from_json(contract::query(deps.as_ref(), mock_env(), QueryMsg::OrderForAddressIds {
+        order_type: OrderType::Limit,
+        address: dave
+    }).unwrap()).unwrap();
+    assert!(ids.ids.is_empty());
+    let response: OrderListResponse = from_json(contract::query(deps.as_ref(), mock_env(), QueryMsg::OrdersByPool {
+        order_type: OrderType::Limit,
+        pool_address: pool,
+        start_after: None,
+        limit: None
+    }).unwrap()).unwrap();
+    assert_eq!(response.orders.len(), 1);
+
+    assert_eq!(util::get_escrowed(deps.as_ref().storage, &token1_denom).unwrap(), Uint128::from(5_000_000u128));
+    assert_eq!(util::get_escrowed(deps.as_ref().storage, &token2_denom).unwrap(), Uint128::from(6_940_000u128));
+}
+
+#[test]
+fn migrate_keeps_the_fee_free_terms_of_baseline_orders() {
+    let mut deps = mock_dependencies();
+    let storage = deps.as_mut().storage;
+    set_contract_version(storage, "universe_orders", "1.0.0").unwrap();
+    baseline::CONFIG.save(storage, &baseline::Config { owner: Addr::unchecked(OWNER), enabled: true }).unwrap();
+
+    let token1_denom = Denom::Native(TOKEN1.to_string());
+    let alice = Addr::unchecked("alice");
+    baseline::LIMIT_ORDERS.save(storage, (alice.clone(), 0), &baseline::LimitConfig {
+        msg: baseline::LimitMsg { token1_denom: token1_denom.clone(), pool_address: Addr::unchecked("pool"), take_profit_percentage: 5u64 },
+        token2_denom: Denom::Native(TOKEN2.to_string()),
+        initial_token1_amount: Uint128::from(1_000_000u128),
+        token1_amount: Uint128::zero(),
+        token2_amount: Uint128::from(990_000u128),
+        avg_buy_price: Uint128::from(1_010_101u128),
+        target_buy_price: Uint128::from(1_060_606u128),
+        finished: false
+    }).unwrap();
+    baseline::LIMIT_ORDERS_COUNT.save(storage, alice.clone(), &(vec![0], 1)).unwrap();
+
+    contract::migrate(deps.as_mut(), mock_env(), MigrateMsg { fee_recipient: Some(Addr::unchecked(TREASURY)) }).unwrap();
+
+    // closing the order at its target pays neither a keeper tip nor a performance fee
+    let storage = deps.as_mut().storage;
+    let output = Uint128::from(1_050_000u128);
+    assert_eq!(util::get_keeper_tip(storage, &Addr::unchecked(KEEPER), &alice, output).unwrap(), Uint128::zero());
+    let (fee, _fee_recipient) = util::take_performance_fee(storage, &token1_denom, Uint128::from(1_000_000u128), output).unwrap();
+    assert_eq!(fee, Uint128::zero());
+}
+
+#[test]
+fn astroport_swap_covers_the_pair_commission() {
+    let mut deps = mock_dependencies();
+    // 0.3% commission taken from the output
+    deps.querier.update_wasm(|_query| SystemResult::Ok(ContractResult::Ok(to_json_binary(&SimulationResponse {
+        return_amount: Uint128::from(997_000u128),
+        spread_amount: Uint128::zero(),
+        commission_amount: Uint128::from(3_000u128)
+    }).unwrap())));
+    let querier = QuerierWrapper::<Empty>::new(&deps.querier);
+    let pair = Addr::unchecked("pair");
+    let denom = Denom::Native(TOKEN1.to_string());
+
+    let swap = |min_output: u128| -> (Option<Decimal>, Option<Decimal>) {
+        let messages = dex::adapter(DexKind::Astroport).swap_messages(querier, &pair, &denom, true, Uint128::from(1_000_000u128), Uint128::from(min_output)).unwrap();
+        match &messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => match from_json(msg).unwrap() {
+                PairExecuteMsg::Swap { belief_price, max_spread, .. } => (belief_price, max_spread)
+            },
+            message => panic!("not a swap: {:?}", message)
+        }
+    };
+
+    // the pair checks the output before its commission, which must be 997_000 / 0.997
+    assert_eq!(swap(997_000), (Some(Decimal::one()), Some(Decimal::zero())));
+    // no minimum takes the widest spread the pair accepts rather than its default
+    assert_eq!(swap(0), (None, Some(Decimal::percent(50))));
+}
+
+// Wasmswap pool quoting a fixed price, token1 per token2 multiplied by 1000000
+fn fixed_price_pool(deps: &mut OwnedDeps<MemoryStorage, MockApi, MockQuerier>, price: u128) {
+    use wasmswap::msg::{InfoResponse, QueryMsg, Token1ForToken2PriceResponse, Token2ForToken1PriceResponse};
+    deps.querier.update_wasm(move |query| {
+        let msg = match query {
+            WasmQuery::Smart { msg, .. } => msg,
+            _ => panic!("unexpected query")
+        };
+        let response = match from_json(msg).unwrap() {
+            QueryMsg::Info {} => to_json_binary(&InfoResponse {
+                token1_reserve: Uint128::zero(),
+                token1_denom: Denom::Native(TOKEN1.to_string()),
+                token2_reserve: Uint128::zero(),
+                token2_denom: Denom::Native(TOKEN2.to_string()),
+                lp_token_supply: Uint128::zero(),
+                lp_token_address: String::from("lp")
+            }),
+            QueryMsg::Token1ForToken2Price { token1_amount } => to_json_binary(&Token1ForToken2PriceResponse {
+                token2_amount: token1_amount * Uint128::from(1_000_000u128) / Uint128::from(price)
+            }),
+            QueryMsg::Token2ForToken1Price { token2_amount } => to_json_binary(&Token2ForToken1PriceResponse {
+                token1_amount: token2_amount * Uint128::from(price) / Uint128::from(1_000_000u128)
+            }),
+            _ => panic!("unexpected query")
+        };
+        SystemResult::Ok(ContractResult::Ok(response.unwrap()))
+    });
+}
+
+fn swap_min_output(messages: &[CosmosMsg]) -> Uint128 {
+    match &messages[0] {
+        CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => match from_json(msg).unwrap() {
+            wasmswap::msg::ExecuteMsg::Swap { min_output, .. } => min_output,
+            message => panic!("not a swap: {:?}", message)
+        },
+        message => panic!("not a swap: {:?}", message)
+    }
+}
+
+#[test]
+fn stop_loss_swap_fills_once_the_price_gapped_past_it() {
+    let mut deps = mock_dependencies();
+    // 0.9 token1 per token2, well under a stop loss at 0.95
+    fixed_price_pool(&mut deps, 900_000);
+    let querier = QuerierWrapper::<Empty>::new(&deps.querier);
+    let pool = Addr::unchecked("pool");
+    let token2 = Denom::Native(TOKEN2.to_string());
+    let amount = Uint128::from(1_000_000u128);
+
+    // a stop loss carries no limit, the live quote less the slippage bounds it
+    let (swap_amount, _denom, messages) = util::get_swap_amount_and_denom_and_message(querier, DexKind::Wasmswap, pool.clone(), token2.clone(), amount, None, 100).unwrap();
+    assert_eq!(swap_amount, Uint128::from(900_000u128));
+    assert_eq!(swap_min_output(&messages), Uint128::from(891_000u128));
+
+    // held to the stop price the sale could never fill
+    let stop_output = util::get_output_at_price(amount, Uint128::from(950_000u128), false);
+    let err = util::get_swap_amount_and_denom_and_message(querier, DexKind::Wasmswap, pool.clone(), token2.clone(), amount, Some(stop_output), 100).unwrap_err();
+    assert_eq!(err, ContractError::SlippageExceeded {});
+
+    // a user limit under the live quote raises the bound, without a slippage discount
+    let (_swap_amount, _denom, messages) = util::get_swap_amount_and_denom_and_message(querier, DexKind::Wasmswap, pool, token2, amount, Some(Uint128::from(895_000u128)), 100).unwrap();
+    assert_eq!(swap_min_output(&messages), Uint128::from(895_000u128));
+}
@@ -0,0 +1,462 @@
+use cosmwasm_std::{Addr, Uint128};
+use cw20::Denom;
+
+use universe_orders::ContractError;
+use universe_orders::msg::{
+    BracketConfig, BracketExit, BracketMsg, DcaConfig, DcaMsg, DexKind, GridConfig, GridLevelState, GridMsg, LimitConfig, LimitMsg, LimitPhase, SmartConfig, SmartMsg, TwapConfig, TwapMsg
+};
+use universe_orders::ordergroup::{OrderStrategy, Quote, Transition};
+use universe_orders::state::SwapKind;
+use universe_orders::util;
+
+const TOKEN1: &str = "ujuno";
+const TOKEN2: &str = "uatom";
+const POOL: &str = "pool";
+
+// A pool without depth or fee, always at the same price, token1 per token2 multiplied by 1000000
+struct MockQuote {
+    price: u128
+}
+
+impl Quote for MockQuote {
+    fn buy(&self, token1_amount: Uint128) -> Result<Uint128, ContractError> {
+        Ok(token1_amount * Uint128::from(1_000_000u128) / Uint128::from(self.price))
+    }
+
+    fn sell(&self, token2_amount: Uint128) -> Result<Uint128, ContractError> {
+        Ok(token2_amount * Uint128::from(self.price) / Uint128::from(1_000_000u128))
+    }
+}
+
+fn at(price: u128) -> MockQuote {
+    MockQuote { price }
+}
+
+fn start<S: OrderStrategy>(msg: S::Msg, token1_amount: u128, quote: &MockQuote) -> (S, Transition) {
+    S::on_start(msg, Uint128::from(token1_amount), Denom::Native(TOKEN2.to_string()), DexKind::Wasmswap, quote, 1_000).unwrap()
+}
+
+fn swap(transition: &Transition) -> Option<(SwapKind, Uint128)> {
+    transition.swap.clone()
+}
+
+fn attribute(transition: &Transition, key: &str) -> Option<String> {
+    transition.attributes.iter().find(|attr| attr.key == key).map(|attr| attr.value.clone())
+}
+
+fn limit_msg(stop_loss_percentage: Option<u64>, trail_percentage: Option<u64>, entry_price: Option<u128>) -> LimitMsg {
+    LimitMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        take_profit_percentage: 10,
+        stop_loss_percentage,
+        trail_percentage,
+        entry_price: entry_price.map(Uint128::from),
+        max_slippage_bps: None,
+        expires_at: None
+    }
+}
+
+#[test]
+fn limit_takes_profit_above_the_target() {
+    let (mut order, transition) = start::<LimitConfig>(limit_msg(None, None, None), 1_000_000, &at(1_000_000));
+    assert_eq!(swap(&transition), Some((SwapKind::Start, Uint128::from(1_000_000u128))));
+    assert_eq!(order.target_buy_price, Uint128::from(1_100_000u128));
+
+    let transition = order.on_price(&at(1_050_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "waiting");
+    assert!(!order.finished);
+
+    let transition = order.on_price(&at(1_200_000), 0, 1_120).unwrap();
+    assert_eq!(transition.action, "success");
+    assert_eq!(swap(&transition), Some((SwapKind::Finish, Uint128::from(1_000_000u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(1_100_000u128)));
+    assert!(order.finished);
+}
+
+#[test]
+fn limit_stops_the_loss_below_the_stop_price() {
+    let (mut order, _transition) = start::<LimitConfig>(limit_msg(Some(5), None, None), 1_000_000, &at(1_000_000));
+    assert_eq!(order.stop_loss_price, Some(Uint128::from(950_000u128)));
+
+    let transition = order.on_price(&at(960_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "waiting");
+
+    // the stop loss sells at whatever the pool pays, however far the price gapped past it
+    let transition = order.on_price(&at(900_000), 0, 1_120).unwrap();
+    assert_eq!(transition.action, "stop_loss");
+    assert_eq!(swap(&transition), Some((SwapKind::Finish, Uint128::from(1_000_000u128))));
+    assert_eq!(transition.limit_price, None);
+    assert!(order.finished);
+}
+
+#[test]
+fn limit_trails_the_peak_past_the_target() {
+    let (mut order, _transition) = start::<LimitConfig>(limit_msg(None, Some(5), None), 1_000_000, &at(1_000_000));
+
+    // above the target the sale waits while the price keeps within the trail
+    let transition = order.on_price(&at(1_200_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "waiting");
+    assert_eq!(order.peak_price, Some(Uint128::from(1_200_000u128)));
+    assert_eq!(order.trail_trigger_price, Some(Uint128::from(1_140_000u128)));
+
+    let transition = order.on_price(&at(1_150_000), 0, 1_120).unwrap();
+    assert_eq!(transition.action, "waiting");
+    assert_eq!(order.peak_price, Some(Uint128::from(1_200_000u128)));
+
+    let transition = order.on_price(&at(1_130_000), 0, 1_180).unwrap();
+    assert_eq!(transition.action, "success");
+    assert_eq!(transition.limit_price, None);
+    assert!(order.finished);
+}
+
+#[test]
+fn limit_buys_once_the_entry_price_is_reached() {
+    let (mut order, transition) = start::<LimitConfig>(limit_msg(None, None, Some(900_000)), 1_000_000, &at(1_000_000));
+    assert_eq!(transition.action, "entry");
+    assert_eq!(swap(&transition), None);
+    assert_eq!(order.phase, LimitPhase::Entry);
+    assert_eq!(order.token1_amount, Uint128::from(1_000_000u128));
+
+    let transition = order.on_price(&at(950_000), 100, 1_060).unwrap();
+    assert_eq!(transition.action, "waiting");
+
+    // the keeper tip stays behind in token1, the targets follow the price bought at
+    let transition = order.on_price(&at(800_000), 100, 1_120).unwrap();
+    assert_eq!(transition.action, "entry");
+    assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(990_000u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(900_000u128)));
+    assert_eq!(order.phase, LimitPhase::TakeProfit);
+    assert_eq!(order.token1_amount, Uint128::from(10_000u128));
+    assert_eq!(order.avg_buy_price, Uint128::from(800_000u128));
+    assert_eq!(order.target_buy_price, Uint128::from(880_000u128));
+}
+
+#[test]
+fn limit_stop_before_the_entry_refunds_the_deposit() {
+    let (mut order, _transition) = start::<LimitConfig>(limit_msg(None, None, Some(900_000)), 1_000_000, &at(1_000_000));
+
+    let transition = order.on_stop(&at(1_000_000)).unwrap();
+    assert_eq!(transition.action, "success");
+    assert_eq!(swap(&transition), None);
+    assert_eq!(transition.refund, Uint128::from(1_000_000u128));
+    assert_eq!(order.token1_amount, Uint128::zero());
+    assert!(order.finished);
+}
+
+#[test]
+fn smart_buys_down_the_dca_ladder() {
+    let msg = SmartMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        take_profit_percentage: 10,
+        stop_loss_percentage: None,
+        initial_token1_amount: Uint128::from(1_000_000u128),
+        num_dca_orders: 2,
+        dca_step: 10,
+        dca_step_multiplier: 1,
+        dca_order_size: Uint128::from(1_000_000u128),
+        dca_order_size_multiplier: 1,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let (mut order, transition) = start::<SmartConfig>(msg, 3_500_000, &at(1_000_000));
+    assert_eq!(swap(&transition), Some((SwapKind::Start, Uint128::from(1_000_000u128))));
+    assert_eq!(transition.refund, Uint128::from(500_000u128));
+    assert_eq!(order.token1_amount, Uint128::from(2_000_000u128));
+    assert_eq!(order.dca_prices, vec![Uint128::from(900_000u128), Uint128::from(800_000u128)]);
+
+    let transition = order.on_price(&at(950_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "waiting");
+
+    let transition = order.on_price(&at(850_000), 0, 1_120).unwrap();
+    assert_eq!(transition.action, "dca");
+    assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(1_000_000u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(900_000u128)));
+    assert_eq!(order.current_dca_point, 1);
+    assert_eq!(order.token1_amount, Uint128::from(1_000_000u128));
+
+    let transition = order.on_price(&at(700_000), 0, 1_180).unwrap();
+    assert_eq!(transition.action, "dca");
+    assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(1_000_000u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(800_000u128)));
+    assert_eq!(order.current_dca_point, 2);
+    assert_eq!(order.token1_amount, Uint128::zero());
+
+    // the ladder is used up, only the take profit is left
+    let transition = order.on_price(&at(600_000), 0, 1_240).unwrap();
+    assert_eq!(transition.action, "waiting");
+}
+
+#[test]
+fn smart_buys_every_dca_point_passed_in_one_swap() {
+    let msg = SmartMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        take_profit_percentage: 10,
+        stop_loss_percentage: None,
+        initial_token1_amount: Uint128::from(1_000_000u128),
+        num_dca_orders: 2,
+        dca_step: 10,
+        dca_step_multiplier: 1,
+        dca_order_size: Uint128::from(1_000_000u128),
+        dca_order_size_multiplier: 1,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let (mut order, _transition) = start::<SmartConfig>(msg, 3_000_000, &at(1_000_000));
+
+    let transition = order.on_price(&at(700_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "dca");
+    assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(2_000_000u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(900_000u128)));
+    assert_eq!(order.current_dca_point, 2);
+}
+
+#[test]
+fn smart_keeps_the_keeper_tip_back_from_each_dca_buy() {
+    let msg = SmartMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        take_profit_percentage: 10,
+        stop_loss_percentage: None,
+        initial_token1_amount: Uint128::from(1_000_000u128),
+        num_dca_orders: 2,
+        dca_step: 10,
+        dca_step_multiplier: 1,
+        dca_order_size: Uint128::from(1_000_000u128),
+        dca_order_size_multiplier: 1,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let (mut order, _transition) = start::<SmartConfig>(msg, 3_000_000, &at(1_000_000));
+
+    for (price, token1_left) in [(850_000u128, 1_000_000u128), (700_000u128, 0u128)] {
+        let transition = order.on_price(&at(price), 10, 1_060).unwrap();
+        assert_eq!(transition.action, "dca");
+        assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(999_000u128))));
+        assert_eq!(order.keeper_tip, Uint128::from(1_000u128));
+
+        // the reply pays the keeper exactly the tip kept back
+        order.token1_amount -= order.keeper_tip;
+        order.keeper_tip = Uint128::zero();
+        assert_eq!(order.token1_amount, Uint128::from(token1_left));
+    }
+}
+
+#[test]
+fn smart_refuses_a_ladder_the_deposit_does_not_cover() {
+    // two dca points of 2_000_000 each, the deposit only keeps 2_000_000 for them
+    let msg = SmartMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        take_profit_percentage: 10,
+        stop_loss_percentage: None,
+        initial_token1_amount: Uint128::from(1_000_000u128),
+        num_dca_orders: 2,
+        dca_step: 10,
+        dca_step_multiplier: 1,
+        dca_order_size: Uint128::from(2_000_000u128),
+        dca_order_size_multiplier: 1,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let started = SmartConfig::on_start(msg, Uint128::from(3_000_000u128), Denom::Native(TOKEN2.to_string()), DexKind::Wasmswap, &at(1_000_000), 1_000);
+    assert_eq!(started.err(), Some(ContractError::LadderUnderfunded {}));
+}
+
+#[test]
+fn grid_rearms_the_neighbouring_level_after_a_fill() {
+    let msg = GridMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        total_amount: Uint128::from(2_000_000u128),
+        num_grid_pairs: 2,
+        price_range_percentage: Some(10),
+        lower_price: None,
+        upper_price: None,
+        spacing: None,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let (mut order, transition) = start::<GridConfig>(msg, 2_000_000, &at(1_000_000));
+    assert_eq!(swap(&transition), Some((SwapKind::Start, Uint128::from(1_000_000u128))));
+    let prices: Vec<u128> = order.levels.iter().map(|level| level.price.u128()).collect();
+    assert_eq!(prices, vec![900_000, 950_000, 1_000_000, 1_050_000, 1_100_000]);
+    assert_eq!(order.order_amount, Uint128::from(500_000u128));
+
+    // the first buy level fills and arms a sell on the start level above it
+    let transition = order.on_price(&at(940_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "buy");
+    assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(500_000u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(950_000u128)));
+    assert_eq!(order.levels[1].state, GridLevelState::Idle);
+    assert_eq!(order.levels[2].state, GridLevelState::Sell);
+    assert!(order.levels[2].rearmed);
+    assert_eq!(order.levels[0].state, GridLevelState::Buy);
+    assert_eq!(order.token1_amount, Uint128::from(500_000u128));
+    assert_eq!(order.round_trips, 0);
+
+    // selling on the re-armed level completes a round trip and arms the buy below again
+    let transition = order.on_price(&at(1_010_000), 0, 1_120).unwrap();
+    assert_eq!(transition.action, "sell");
+    assert_eq!(swap(&transition), Some((SwapKind::Sell, Uint128::from(495_049u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(1_000_000u128)));
+    assert_eq!(attribute(&transition, "round_trips"), Some(String::from("1")));
+    assert_eq!(order.round_trips, 1);
+    assert_eq!(order.levels[2].state, GridLevelState::Idle);
+    assert_eq!(order.levels[1].state, GridLevelState::Buy);
+    assert!(order.levels[1].rearmed);
+    assert_eq!(order.levels[3].state, GridLevelState::Sell);
+}
+
+#[test]
+fn grid_refuses_levels_left_without_an_order_amount() {
+    // 3 token1 left after the first swap can't be split over 4 grid pairs
+    let msg = GridMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        total_amount: Uint128::from(6u128),
+        num_grid_pairs: 4,
+        price_range_percentage: Some(10),
+        lower_price: None,
+        upper_price: None,
+        spacing: None,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let started = GridConfig::on_start(msg, Uint128::from(6u128), Denom::Native(TOKEN2.to_string()), DexKind::Wasmswap, &at(1_000_000), 1_000);
+    assert_eq!(started.err(), Some(ContractError::InsufficientAmountForGridOrder {}));
+}
+
+#[test]
+fn dca_catches_up_on_the_rounds_due() {
+    let msg = DcaMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        round_amount: Uint128::from(100u128),
+        interval: 60,
+        num_rounds: 3,
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let (mut order, transition) = start::<DcaConfig>(msg, 350, &at(1_000_000));
+    assert_eq!(swap(&transition), Some((SwapKind::Start, Uint128::from(100u128))));
+    assert_eq!(transition.refund, Uint128::from(50u128));
+    assert_eq!(order.token1_amount, Uint128::from(200u128));
+    assert_eq!(order.last_execution, 1_000);
+
+    let transition = order.on_price(&at(1_000_000), 100, 1_030).unwrap();
+    assert_eq!(transition.action, "waiting");
+
+    // two rounds came due, they are bought at once with the keeper tip kept back
+    let transition = order.on_price(&at(1_000_000), 100, 1_130).unwrap();
+    assert_eq!(transition.action, "round");
+    assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(198u128))));
+    assert_eq!(attribute(&transition, "rounds"), Some(String::from("2")));
+    assert_eq!(order.keeper_tip, Uint128::from(2u128));
+    assert_eq!(order.token1_amount, Uint128::from(2u128));
+    assert_eq!(order.rounds_executed, 3);
+    assert_eq!(order.last_execution, 1_120);
+
+    let transition = order.on_stop(&at(1_000_000)).unwrap();
+    assert_eq!(transition.refund, Uint128::from(2u128));
+    assert!(order.finished);
+}
+
+#[test]
+fn twap_sells_a_slice_only_above_the_min_price() {
+    let msg = TwapMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        total_amount: Uint128::from(1_000u128),
+        num_slices: 4,
+        interval: 60,
+        min_price: Uint128::from(900_000u128),
+        max_slippage_bps: None,
+        expires_at: None
+    };
+    let (mut order, transition) = start::<TwapConfig>(msg, 1_000, &at(1_000_000));
+    assert_eq!(swap(&transition), None);
+    assert_eq!(attribute(&transition, "slice_amount"), Some(String::from("250")));
+
+    let transition = order.on_price(&at(1_200_000), 0, 1_000).unwrap();
+    assert_eq!(transition.action, "price_low");
+    assert_eq!(order.slices_filled, 0);
+
+    let transition = order.on_price(&at(1_000_000), 0, 1_010).unwrap();
+    assert_eq!(transition.action, "slice");
+    assert_eq!(swap(&transition), Some((SwapKind::Buy, Uint128::from(250u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(1_111_111u128)));
+    assert_eq!(order.token1_amount, Uint128::from(750u128));
+    // the swap must return what min_price asks for, 250 * 0.9
+    assert_eq!(util::get_output_at_price(Uint128::from(250u128), Uint128::from(1_111_111u128), true), Uint128::from(225u128));
+
+    let transition = order.on_price(&at(1_000_000), 0, 1_040).unwrap();
+    assert_eq!(transition.action, "waiting");
+
+    // a unit short of the floor is not sold, there is no slippage below min_price
+    let transition = order.on_price(&at(1_111_200), 0, 1_080).unwrap();
+    assert_eq!(transition.action, "price_low");
+    assert_eq!(order.slices_filled, 1);
+}
+
+fn bracket_msg() -> BracketMsg {
+    BracketMsg {
+        token1_denom: Denom::Native(TOKEN1.to_string()),
+        pool_address: Addr::unchecked(POOL),
+        take_profit_percentage: 10,
+        stop_loss_percentage: 5,
+        max_slippage_bps: None,
+        expires_at: None
+    }
+}
+
+#[test]
+fn bracket_takes_profit_no_lower_than_its_price() {
+    let (mut order, transition) = start::<BracketConfig>(bracket_msg(), 1_000_000, &at(1_000_000));
+    assert_eq!(swap(&transition), Some((SwapKind::Start, Uint128::from(1_000_000u128))));
+    assert_eq!(order.take_profit_price, Uint128::from(1_100_000u128));
+    assert_eq!(order.stop_loss_price, Uint128::from(950_000u128));
+
+    let transition = order.on_price(&at(1_000_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "waiting");
+
+    let transition = order.on_price(&at(1_200_000), 0, 1_120).unwrap();
+    assert_eq!(transition.action, "take_profit");
+    assert_eq!(swap(&transition), Some((SwapKind::Finish, Uint128::from(1_000_000u128))));
+    assert_eq!(transition.limit_price, Some(Uint128::from(1_100_000u128)));
+    assert_eq!(order.exit, Some(BracketExit::TakeProfit));
+    assert!(order.finished);
+}
+
+#[test]
+fn bracket_stop_loss_fills_when_the_price_gaps_past_it() {
+    let (mut order, _transition) = start::<BracketConfig>(bracket_msg(), 1_000_000, &at(1_000_000));
+
+    // the price falls straight through the stop loss at 950_000
+    let transition = order.on_price(&at(600_000), 0, 1_060).unwrap();
+    assert_eq!(transition.action, "stop_loss");
+    assert_eq!(swap(&transition), Some((SwapKind::Finish, Uint128::from(1_000_000u128))));
+    assert_eq!(transition.limit_price, None);
+    assert_eq!(order.exit, Some(BracketExit::StopLoss));
+    assert!(order.finished);
+}
+
+#[test]
+fn bracket_stop_sells_at_the_live_price() {
+    // between the levels
+    let (mut order, _transition) = start::<BracketConfig>(bracket_msg(), 1_000_000, &at(1_000_000));
+    let transition = order.on_stop(&at(1_000_000)).unwrap();
+    assert_eq!(transition.action, "success");
+    assert_eq!(transition.limit_price, None);
+    assert_eq!(order.exit, None);
+
+    // past the take profit the exit is recorded, the stop still takes any price
+    let (mut order, _transition) = start::<BracketConfig>(bracket_msg(), 1_000_000, &at(1_000_000));
+    let transition = order.on_stop(&at(1_200_000)).unwrap();
+    assert_eq!(transition.action, "take_profit");
+    assert_eq!(transition.limit_price, None);
+    assert_eq!(order.exit, Some(BracketExit::TakeProfit));
+    assert!(order.finished);
+}